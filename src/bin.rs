@@ -1,26 +1,162 @@
-use clap::Parser;
-use instance_packager::{build_jsons_for_core, find_cores_with_package_json, PACKAGER_NAME};
+use clap::{Parser, Subcommand};
+use instance_packager::{
+    build_jsons_for_core, build_jsons_for_cores, find_cores_with_package_json, validate_config,
+    validate_core_config, BuildOptions, CollisionStrategy, Event, FolderWarning, PackagerError,
+    Severity, ValidationIssue, PACKAGER_NAME,
+};
 use question::{Answer, Question};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::exit;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    #[arg(index = 1)]
-    pocket_root_path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(index = 1, required = true)]
+    pocket_root_path: Option<PathBuf>,
     #[arg(short, long)]
     all: bool,
     #[arg(short, long)]
     file_tree_copy: bool,
+    /// Remove instance files made by earlier runs for games which have since been moved or deleted
+    #[arg(short, long)]
+    clean: bool,
+    /// What to do when two games would get the same instance file name: error, suffix or prefix
+    #[arg(long, default_value = "error")]
+    collisions: CollisionStrategy,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check instance-packager.json files for mistakes without building anything
+    Validate {
+        /// A Pocket root to check every core in, or a single instance-packager.json
+        path: PathBuf,
+    },
+}
+
+fn print_event(event: Event) {
+    match event {
+        Event::InstanceWritten { path, .. } => println!("Wrote {}", path.display()),
+        Event::SlotLimitExceeded { path, message } => {
+            println!("Skipped {} \n {message}", path.display())
+        }
+        Event::InstanceRemoved { path } => println!("Removed {}", path.display()),
+        Event::OutputCollision { path, folders } => {
+            println!("Collision on {} from:", path.display());
+            for folder in folders {
+                println!(" {}", folder.display());
+            }
+        }
+        Event::FolderWarning { folder, warning } => {
+            let message = match warning {
+                FolderWarning::CueCaseMismatch {
+                    cue,
+                    referenced,
+                    found,
+                } => format!("{cue} refers to \"{referenced}\" but the file is \"{found}\""),
+                FolderWarning::UnreferencedFile { cue, file } => {
+                    format!("{file} isn't in {cue} so was left out")
+                }
+                FolderWarning::CaseOnlyDifference { file, other } => {
+                    format!("{file} & {other} only differ by case, so can't both be on the SD card")
+                }
+                FolderWarning::UndeclaredSlot { slot_id } => {
+                    format!("a memory write reads slot id {slot_id}, which no data slot has, so it was left out")
+                }
+            };
+            println!("Warning for {}: {message}", folder.display())
+        }
+        Event::IoError { path, message } => eprintln!("Error with {}: {message}", path.display()),
+        Event::CoreFinished { core_name, summary } => println!(
+            "{core_name}: {} new, {} updated, {} unchanged, {} removed, {} skipped, {} rejected, {} collisions, {} errors, {} warnings\n",
+            summary.new,
+            summary.updated,
+            summary.unchanged,
+            summary.removed,
+            summary.slot_limit_exceeded,
+            summary.folders_rejected,
+            summary.output_collisions,
+            summary.io_errors,
+            summary.warnings
+        ),
+        _ => {}
+    }
+}
+
+fn build_core(path: &Path, core_name: &str, options: &BuildOptions) {
+    let result = build_jsons_for_core(path, core_name, options, &print_event);
+
+    if let Err(err) = result {
+        eprintln!("Failed to build {core_name}: {err}");
+    }
+}
+
+/// Prints the issues for one file, returning whether any of them were errors.
+fn print_issues(file_path: &Path, issues: &[ValidationIssue]) -> bool {
+    for issue in issues {
+        println!("{}:{issue}", file_path.display());
+    }
+    issues.iter().any(|i| i.severity == Severity::Error)
+}
+
+fn validate(path: &Path) -> Result<bool, PackagerError> {
+    if path.is_file() {
+        let source =
+            std::fs::read_to_string(path).map_err(|source| PackagerError::UnreadableConfig {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        return Ok(print_issues(path, &validate_config(&source)));
+    }
+
+    let mut has_errors = false;
+    for core_name in find_cores_with_package_json(path)? {
+        let file_path = Path::new("Cores").join(&core_name).join(PACKAGER_NAME);
+        let issues = validate_core_config(path, &core_name)?;
+        if issues.is_empty() {
+            println!("{}: ok", file_path.display());
+        }
+        has_errors |= print_issues(&file_path, &issues);
+    }
+    Ok(has_errors)
 }
 
 fn main() {
     let args = Args::parse();
-    let path = args.pocket_root_path;
-    let cores_list = find_cores_with_package_json(&path).unwrap();
+
+    if let Some(Command::Validate { path }) = args.command {
+        match validate(&path) {
+            Ok(false) => return,
+            Ok(true) => exit(1),
+            Err(err) => {
+                eprintln!("{err}");
+                exit(1);
+            }
+        }
+    }
+
+    let path = args
+        .pocket_root_path
+        .expect("clap requires the path without a subcommand");
+    let cores_list = match find_cores_with_package_json(&path) {
+        Ok(cores_list) => cores_list,
+        Err(err) => {
+            eprintln!("{err}");
+            exit(1);
+        }
+    };
     let core_count = cores_list.len();
 
-    if cores_list.len() == 0 {
+    if cores_list.is_empty() {
         println!("Found 0 cores with an {PACKAGER_NAME}, exiting...");
         return;
     }
@@ -29,22 +165,20 @@ fn main() {
     for (i, core_name) in cores_list.iter().enumerate() {
         println!("{}: {core_name}", i + 1);
     }
-    println!("");
+    println!();
+
+    let options = BuildOptions {
+        keep_file_tree: args.file_tree_copy,
+        clean_orphans: args.clean,
+        collision_strategy: args.collisions,
+    };
 
     let do_all_cores = || {
-        for core_name in &cores_list {
-            build_jsons_for_core(
-                &path,
-                &core_name,
-                args.file_tree_copy,
-                |file_name| {
-                    println!("Wrote {}", file_name);
-                },
-                |file_name, message| {
-                    println!("Skipped {file_name} \n {message}");
-                },
-            )
-            .unwrap();
+        let results = build_jsons_for_cores(&path, &cores_list, &options, &print_event);
+        for (core_name, result) in cores_list.iter().zip(results) {
+            if let Err(err) = result {
+                eprintln!("Failed to build {core_name}: {err}");
+            }
         }
     };
 
@@ -57,7 +191,7 @@ fn main() {
     let mut all_choices = vec!["all"];
     all_choices.extend(numbers.iter().map(|s| s.as_str()));
 
-    if let Some(answer) = Question::new("Pick a core or all?")
+    if let Some(Answer::RESPONSE(res)) = Question::new("Pick a core or all?")
         .acceptable(all_choices)
         .until_acceptable()
         .default(Answer::RESPONSE("all".to_string()))
@@ -66,29 +200,15 @@ fn main() {
         .clarification("Enter \"all\" or the number listed of a core above")
         .ask()
     {
-        match answer {
-            Answer::RESPONSE(res) => match res.as_str() {
-                "all" => {
-                    do_all_cores();
-                }
-                _ => {
-                    let index: usize = res.parse().unwrap();
-                    let core_name = &cores_list[index - 1];
-                    build_jsons_for_core(
-                        &path,
-                        &core_name,
-                        args.file_tree_copy,
-                        |file_name| {
-                            println!("Wrote {}", file_name);
-                        },
-                        |file_name, message| {
-                            println!("Skipped {file_name} \n {message}");
-                        },
-                    )
-                    .unwrap();
-                }
-            },
-            _ => {}
+        match res.as_str() {
+            "all" => {
+                do_all_cores();
+            }
+            _ => {
+                let index: usize = res.parse().unwrap();
+                let core_name = &cores_list[index - 1];
+                build_core(&path, core_name, &options);
+            }
         }
     }
 }
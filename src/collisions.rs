@@ -0,0 +1,143 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{relative_to, Event, PlannedInstance, Reporter};
+
+/// What to do when more than one game would be written to the same instance file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionStrategy {
+    /// Report the collision & don't write any of the colliding instance files
+    #[default]
+    Error,
+    /// Keep the first name, the others get ` (2)`, ` (3)` etc. added
+    NumericSuffix,
+    /// Prefix every colliding file with the name of the folder it came from, e.g. `usa - Game.json`
+    ParentPrefix,
+}
+
+impl FromStr for CollisionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(CollisionStrategy::Error),
+            "suffix" => Ok(CollisionStrategy::NumericSuffix),
+            "prefix" => Ok(CollisionStrategy::ParentPrefix),
+            _ => Err(format!(
+                "unknown collision strategy \"{s}\", expected error, suffix or prefix"
+            )),
+        }
+    }
+}
+
+/// Output paths which have already been written to, along with the game folder they came from.
+///
+/// Used to stop cores which share an output folder clobbering each other.
+pub(crate) type TakenPaths = HashMap<String, PathBuf>;
+
+// the Pocket's SD card is exFAT, so `Game.json` & `game.json` are the same file
+pub(crate) fn collision_key(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+pub(crate) fn resolve_collisions(
+    root_path: &Path,
+    plan: &mut [PlannedInstance],
+    taken: &TakenPaths,
+    strategy: CollisionStrategy,
+    reporter: &impl Reporter,
+) {
+    let mut groups: Vec<(String, Vec<usize>)> = vec![];
+    let mut group_index: HashMap<String, usize> = HashMap::new();
+
+    for (index, planned) in plan.iter().enumerate() {
+        if planned.slot_limit_exceeded.is_some() {
+            continue;
+        }
+        let key = collision_key(&planned.output_path);
+        match group_index.get(&key) {
+            Some(&group) => groups[group].1.push(index),
+            None => {
+                group_index.insert(key.clone(), groups.len());
+                groups.push((key, vec![index]));
+            }
+        }
+    }
+
+    let mut used: HashSet<String> = taken.keys().cloned().collect();
+    used.extend(group_index.into_keys());
+
+    for (key, indices) in groups {
+        let external = taken.get(&key);
+        if indices.len() < 2 && external.is_none() {
+            continue;
+        }
+
+        reporter.report(Event::OutputCollision {
+            path: relative_to(&plan[indices[0]].output_path, root_path).to_path_buf(),
+            folders: external
+                .into_iter()
+                .chain(indices.iter().map(|&i| &plan[i].source_folder))
+                .map(|folder| relative_to(folder, root_path).to_path_buf())
+                .collect(),
+        });
+
+        match strategy {
+            CollisionStrategy::Error => {
+                for &index in &indices {
+                    plan[index].output_collision = true;
+                }
+            }
+            CollisionStrategy::NumericSuffix => {
+                // the first one keeps its name unless another core already has it
+                let skip = if external.is_some() { 0 } else { 1 };
+                for &index in indices.iter().skip(skip) {
+                    let renamed = with_suffix(&plan[index].output_path, &mut used);
+                    plan[index].output_path = renamed;
+                }
+            }
+            CollisionStrategy::ParentPrefix => {
+                for &index in &indices {
+                    let prefixed = with_parent_prefix(&plan[index]);
+                    plan[index].output_path = if used.insert(collision_key(&prefixed)) {
+                        prefixed
+                    } else {
+                        with_suffix(&prefixed, &mut used)
+                    };
+                }
+            }
+        }
+    }
+}
+
+fn with_suffix(output_path: &Path, used: &mut HashSet<String>) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    (2..)
+        .map(|n| output_path.with_file_name(format!("{stem} ({n}).json")))
+        .find(|path| used.insert(collision_key(path)))
+        .expect("there's always an unused suffix")
+}
+
+/// Uses the game folder's parent when the instance is named after the folder, otherwise the game folder itself.
+fn with_parent_prefix(planned: &PlannedInstance) -> PathBuf {
+    let output_path = &planned.output_path;
+    let stem = output_path.file_stem().unwrap_or_default();
+    let prefix_folder = if planned.source_folder.file_name() == Some(stem) {
+        planned.source_folder.parent()
+    } else {
+        Some(planned.source_folder.as_path())
+    };
+
+    match prefix_folder.and_then(|folder| folder.file_name()) {
+        Some(prefix) => output_path.with_file_name(format!(
+            "{} - {}.json",
+            prefix.to_string_lossy(),
+            stem.to_string_lossy()
+        )),
+        None => output_path.clone(),
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::report::FolderWarning;
+use crate::{relative_to, InstancePackagerDataSlot, PackagerError, RejectReason};
+
+pub(crate) enum CueOrder {
+    Ordered {
+        paths: Vec<PathBuf>,
+        warnings: Vec<FolderWarning>,
+    },
+    Rejected(RejectReason),
+}
+
+/// Orders the files a `Sort::Cue` slot matched by the `FILE` entries of the cue sheet another slot matched.
+///
+/// `slot_matches` are the files each of the `slots` matched, in the same order.
+///
+/// Files the cue sheet doesn't mention are dropped with a warning, a `FILE` entry for a file that isn't
+/// in the folder at all rejects it.
+pub(crate) fn order_by_cue(
+    folder_path: &Path,
+    slots: &[InstancePackagerDataSlot],
+    slot_matches: &[Vec<PathBuf>],
+    slot_index: usize,
+) -> Result<CueOrder, PackagerError> {
+    let matched = &slot_matches[slot_index];
+    if matched.is_empty() {
+        return Ok(CueOrder::Ordered {
+            paths: vec![],
+            warnings: vec![],
+        });
+    }
+    let Some(cue_path) = find_cue_sheet(slot_matches, slot_index) else {
+        return Ok(CueOrder::Rejected(RejectReason::MissingCueSheet {
+            slot_id: slots[slot_index].id,
+        }));
+    };
+
+    let contents = fs::read(&cue_path).map_err(|source| PackagerError::UnreadableFile {
+        path: cue_path.clone(),
+        source,
+    })?;
+    let contents = String::from_utf8_lossy(&contents);
+    let cue_folder = cue_path.parent().unwrap_or(folder_path);
+    let name = |path: &Path| {
+        relative_to(path, folder_path)
+            .to_string_lossy()
+            .into_owned()
+    };
+    let cue = name(&cue_path);
+
+    let mut paths: Vec<PathBuf> = vec![];
+    let mut warnings = vec![];
+    for file in cue_files(&contents) {
+        // cue sheets made on Windows use `\`
+        let referenced = cue_folder.join(file.replace('\\', "/"));
+        let found = match matched.iter().find(|path| **path == referenced) {
+            Some(path) => Some(path.clone()),
+            None => matched
+                .iter()
+                .find(|path| same_ignoring_case(path, &referenced))
+                .cloned()
+                .or_else(|| find_on_disk(&referenced)),
+        };
+
+        let Some(found) = found else {
+            return Ok(CueOrder::Rejected(RejectReason::CueMissingFile {
+                cue,
+                file: String::from(file),
+            }));
+        };
+        if found != referenced {
+            warnings.push(FolderWarning::CueCaseMismatch {
+                cue: cue.clone(),
+                referenced: name(&referenced),
+                found: name(&found),
+            });
+        }
+        // referenced files the slot didn't match (e.g. `.wav` tracks) belong to other slots
+        if matched.contains(&found) && !paths.contains(&found) {
+            paths.push(found);
+        }
+    }
+
+    for path in matched.iter().filter(|path| !paths.contains(path)) {
+        warnings.push(FolderWarning::UnreferencedFile {
+            cue: cue.clone(),
+            file: name(path),
+        });
+    }
+    Ok(CueOrder::Ordered { paths, warnings })
+}
+
+/// The first `.cue` file matched by any of the other slots.
+fn find_cue_sheet(slot_matches: &[Vec<PathBuf>], slot_index: usize) -> Option<PathBuf> {
+    slot_matches
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != slot_index)
+        .flat_map(|(_, matched)| matched)
+        .find(|path| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("cue"))
+        })
+        .cloned()
+}
+
+fn same_ignoring_case(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+/// Looks through the folder itself rather than using `exists`, which ignores case on macOS & Windows.
+fn find_on_disk(path: &Path) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_string_lossy().to_lowercase();
+    let mut candidates: Vec<PathBuf> = fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|candidate| {
+            candidate
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().to_lowercase() == file_name)
+        })
+        .collect();
+    candidates.sort();
+
+    match candidates.iter().position(|candidate| candidate == path) {
+        Some(exact) => Some(candidates.swap_remove(exact)),
+        None => candidates.into_iter().next(),
+    }
+}
+
+/// The file names from the `FILE "name" TYPE` lines, in order.
+fn cue_files(contents: &str) -> Vec<&str> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches('\u{feff}');
+            let rest = line
+                .get(..4)
+                .filter(|keyword| keyword.eq_ignore_ascii_case("FILE"))
+                .and(line.get(4..))?;
+            let rest = rest.strip_prefix(char::is_whitespace)?.trim_start();
+
+            match rest.strip_prefix('"') {
+                Some(quoted) => quoted.split('"').next(),
+                // unquoted names can't have spaces, the last word is the file type
+                None => rest.split_whitespace().next(),
+            }
+        })
+        .filter(|file| !file.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cue_files() {
+        let cue = "\u{feff}FILE \"Game (Track 1).bin\" BINARY\r\n  TRACK 01 MODE1/2352\r\n    INDEX 01 00:00:00\r\nfile track2.bin BINARY\r\nFILE \"Game (Track 3).wav\" WAVE\r\nREM FILE \"not a file\"\r\n";
+        assert_eq!(
+            cue_files(cue),
+            vec!["Game (Track 1).bin", "track2.bin", "Game (Track 3).wav"]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while finding cores or building instance files.
+#[derive(Debug)]
+pub enum PackagerError {
+    /// The Pocket root doesn't contain a `Cores/` folder
+    MissingCoresFolder(PathBuf),
+    /// The `Cores/` folder is there but its contents couldn't be listed
+    UnreadableCoresFolder { path: PathBuf, source: io::Error },
+    /// The `instance-packager.json` (or another file) couldn't be read
+    UnreadableConfig { path: PathBuf, source: io::Error },
+    /// The `instance-packager.json` isn't valid JSON, or doesn't match the expected format
+    ConfigParse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A file a game's instance depends on, like its cue sheet, couldn't be read. Only that game's folder
+    /// is skipped, it's reported as an `Event::IoError`
+    UnreadableFile { path: PathBuf, source: io::Error },
+    /// A file or folder name that can't be represented as UTF-8
+    NonUtf8Path(PathBuf),
+    /// A `filename` in a data slot isn't a valid glob
+    GlobPattern {
+        pattern: String,
+        source: glob::PatternError,
+    },
+    /// A data slot uses `"sort": "regex"` without a `sort_regex` or `filename_regex`
+    MissingSortRegex { slot_id: usize },
+    /// A data slot's `sort_regex` isn't a valid regex
+    SortRegex {
+        pattern: String,
+        source: regex::Error,
+    },
+    /// A data slot's `filename_regex` isn't a valid regex
+    FilenameRegex {
+        pattern: String,
+        source: regex::Error,
+    },
+    /// A `filename_template` can't be parsed
+    FilenameTemplate { template: String, message: String },
+    /// An `overrides` key is an invalid glob or `/regex/`
+    OverrideKey { key: String, message: String },
+    /// Creating an output folder or writing an instance file failed
+    Write { path: PathBuf, source: io::Error },
+    /// The build was stopped with a `CancellationToken`
+    Cancelled,
+}
+
+impl PackagerError {
+    pub(crate) fn config_parse(path: PathBuf, err: serde_json::Error) -> PackagerError {
+        PackagerError::ConfigParse {
+            path,
+            line: err.line(),
+            column: err.column(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PackagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackagerError::MissingCoresFolder(path) => {
+                write!(f, "Unable to find Cores/ folder in {}", path.display())
+            }
+            PackagerError::UnreadableCoresFolder { path, source } => {
+                write!(
+                    f,
+                    "Unable to list the cores in {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            PackagerError::UnreadableConfig { path, source } => {
+                write!(f, "Unable to read {}: {}", path.display(), source)
+            }
+            PackagerError::ConfigParse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Unable to parse {} (line {}, column {}): {}",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+            PackagerError::UnreadableFile { path, source } => {
+                write!(f, "Unable to read {}: {}", path.display(), source)
+            }
+            PackagerError::NonUtf8Path(path) => {
+                write!(f, "Path is not valid UTF-8: {}", path.display())
+            }
+            PackagerError::GlobPattern { pattern, source } => {
+                write!(f, "Invalid glob pattern \"{}\": {}", pattern, source)
+            }
+            PackagerError::MissingSortRegex { slot_id } => write!(
+                f,
+                "Data slot {} has a regex sort but no sort_regex or filename_regex",
+                slot_id
+            ),
+            PackagerError::SortRegex { pattern, source } => {
+                write!(f, "Invalid sort regex \"{}\": {}", pattern, source)
+            }
+            PackagerError::FilenameRegex { pattern, source } => {
+                write!(f, "Invalid filename regex \"{}\": {}", pattern, source)
+            }
+            PackagerError::FilenameTemplate { template, message } => {
+                write!(f, "Invalid filename template \"{}\": {}", template, message)
+            }
+            PackagerError::OverrideKey { key, message } => {
+                write!(f, "Invalid override key \"{}\": {}", key, message)
+            }
+            PackagerError::Write { path, source } => {
+                write!(f, "Unable to write {}: {}", path.display(), source)
+            }
+            PackagerError::Cancelled => write!(f, "The build was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for PackagerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PackagerError::UnreadableCoresFolder { source, .. } => Some(source),
+            PackagerError::UnreadableConfig { source, .. } => Some(source),
+            PackagerError::UnreadableFile { source, .. } => Some(source),
+            PackagerError::GlobPattern { source, .. } => Some(source),
+            PackagerError::SortRegex { source, .. } => Some(source),
+            PackagerError::FilenameRegex { source, .. } => Some(source),
+            PackagerError::Write { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use glob::Pattern;
+use std::path::Path;
+
+use crate::glob_stuff::NameMatching;
+use crate::PackagerError;
+
+/// A game folder containing a file with this name (even an empty one) is skipped, along with its subfolders.
+pub static NO_INSTANCE_MARKER: &str = ".no-instance";
+
+/// Globs for the files & folders to leave out, like `*.bak`, `_unused/` or `Thumbs.db`.
+///
+/// A glob ending in `/` only matches folders. Globs with a `/` anywhere else are matched against the
+/// whole relative path, others against just the name.
+#[derive(Debug, Default)]
+pub(crate) struct Excludes {
+    globs: Vec<ExcludeGlob>,
+    matching: NameMatching,
+}
+
+#[derive(Debug)]
+struct ExcludeGlob {
+    pattern: Pattern,
+    folders_only: bool,
+    path: bool,
+}
+
+impl Excludes {
+    pub(crate) fn new(globs: &[String], matching: NameMatching) -> Result<Excludes, PackagerError> {
+        let globs = globs
+            .iter()
+            .map(|glob| {
+                let folders_only = glob.ends_with('/');
+                let trimmed = glob.trim_end_matches('/');
+                let pattern = Pattern::new(&matching.normalize(trimmed)).map_err(|source| {
+                    PackagerError::GlobPattern {
+                        pattern: glob.clone(),
+                        source,
+                    }
+                })?;
+                Ok(ExcludeGlob {
+                    pattern,
+                    folders_only,
+                    path: trimmed.contains('/'),
+                })
+            })
+            .collect::<Result<_, PackagerError>>()?;
+        Ok(Excludes { globs, matching })
+    }
+
+    /// `relative` is the path relative to whatever the globs are relative to, with `/` between folders.
+    pub(crate) fn excludes(&self, relative: &str, is_folder: bool) -> bool {
+        let relative = self.matching.normalize(relative);
+        let name = relative.rsplit('/').next().unwrap_or(&relative);
+        let options = self.matching.glob_options();
+        self.globs.iter().any(|glob| {
+            let target = if glob.path { &relative } else { name };
+            (is_folder || !glob.folders_only) && glob.pattern.matches_with(target, options)
+        })
+    }
+}
+
+pub(crate) fn has_marker(folder_path: &Path) -> bool {
+    folder_path.join(NO_INSTANCE_MARKER).is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excludes() {
+        let globs: Vec<String> = ["*.bak", "_unused/", "Thumbs.db", "jp/*/extras"]
+            .iter()
+            .map(|glob| String::from(*glob))
+            .collect();
+        let excludes = Excludes::new(&globs, NameMatching::default()).unwrap();
+
+        assert!(excludes.excludes("Game/save.bak", false));
+        assert!(excludes.excludes("Game/Thumbs.db", false));
+        assert!(excludes.excludes("_unused", true));
+        assert!(excludes.excludes("usa/_unused", true));
+        assert!(!excludes.excludes("Game/_unused", false));
+        assert!(excludes.excludes("jp/Game/extras", true));
+        assert!(!excludes.excludes("jp/Games/Game/extras", true));
+        assert!(!excludes.excludes("Game/game.bin", false));
+
+        assert!(!excludes.excludes("Game/SAVE.BAK", false));
+
+        let matching = NameMatching {
+            case_insensitive: true,
+            normalize_unicode: true,
+        };
+        let excludes = Excludes::new(&[String::from("Pokémon/*.Bin")], matching).unwrap();
+        assert!(excludes.excludes("Poke\u{301}mon/OLD.BIN", false));
+        assert!(!excludes.excludes("Pokemon/OLD.BIN", false));
+
+        assert!(Excludes::new(&[String::from("[abc")], NameMatching::default()).is_err());
+    }
+}
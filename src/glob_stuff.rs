@@ -1,18 +1,201 @@
-use glob::glob;
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+use walkdir::WalkDir;
 
-use std::error;
+use std::borrow::Cow;
+use std::path::{self, Path, PathBuf};
 
-use std::path::PathBuf;
+use crate::exclude::Excludes;
+use crate::{is_hidden, path_str, relative_to, InstancePackagerDataSlot, PackagerError};
 
-pub(crate) fn get_glob_paths(glob_str: &str) -> Result<Vec<PathBuf>, Box<dyn error::Error>> {
-    Ok(glob(&glob_str)?
+/// How names on disk are compared with `filename` globs & `overrides` keys, set by the config's
+/// `case_insensitive` & `normalize_unicode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct NameMatching {
+    pub(crate) case_insensitive: bool,
+    /// Compare names in NFC, so an `é` written as `e` + `´` (as macOS tends to) matches a single `é`
+    pub(crate) normalize_unicode: bool,
+}
+
+impl NameMatching {
+    pub(crate) fn normalize<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.normalize_unicode && !is_nfc(name) {
+            Cow::Owned(name.nfc().collect())
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// What two names are compared by when they have to be exactly the same.
+    pub(crate) fn key(&self, name: &str) -> String {
+        let name = self.normalize(name);
+        if self.case_insensitive {
+            name.to_lowercase()
+        } else {
+            name.into_owned()
+        }
+    }
+
+    /// `*` & `?` don't match `/`, so globs match one folder at a time.
+    pub(crate) fn glob_options(&self) -> MatchOptions {
+        MatchOptions {
+            case_sensitive: !self.case_insensitive,
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        }
+    }
+}
+
+/// The files & folders in a folder matching a glob relative to it, in name order & leaving out hidden ones.
+///
+/// Only the names inside the folder go through the glob, so folders like `Game [!]` or `Why?` are
+/// never read as patterns.
+pub(crate) fn get_glob_paths(
+    folder_path: &Path,
+    glob: &str,
+    matching: NameMatching,
+) -> Result<Vec<PathBuf>, PackagerError> {
+    let pattern =
+        Pattern::new(&matching.normalize(glob)).map_err(|source| PackagerError::GlobPattern {
+            pattern: String::from(glob),
+            source,
+        })?;
+    // only as deep as the glob reaches, unless it has a `**`
+    let depth = if glob.contains("**") {
+        usize::MAX
+    } else {
+        glob.split('/').count()
+    };
+    let options = matching.glob_options();
+
+    Ok(WalkDir::new(folder_path)
+        .min_depth(1)
+        .max_depth(depth)
+        .sort_by_file_name()
         .into_iter()
-        .filter_map(|f| f.ok())
-        .filter(|p| {
-            !p.file_name()
-                .and_then(|f| f.to_str())
-                .unwrap()
-                .starts_with(".")
+        .filter_entry(|e| !is_hidden(e))
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .filter(|path| {
+            let name = relative_name(path, folder_path);
+            pattern.matches_with(&matching.normalize(&name), options)
         })
         .collect())
 }
+
+/// The files a data slot matches in a game folder, those matching its `filename` glob & `filename_regex`
+/// but none of its `exclude` globs.
+pub(crate) fn get_slot_paths(
+    folder_path: &Path,
+    slot: &InstancePackagerDataSlot,
+    matching: NameMatching,
+) -> Result<Vec<PathBuf>, PackagerError> {
+    let paths = get_glob_paths(folder_path, &slot.filename, matching)?;
+    let regex = filename_regex(slot)?;
+    let excludes = Excludes::new(slot.exclude.as_deref().unwrap_or_default(), matching)?;
+
+    Ok(paths
+        .into_iter()
+        .filter(|path| {
+            let name = relative_name(path, folder_path);
+            regex.as_ref().is_none_or(|regex| regex.is_match(&name))
+                && !excludes.excludes(&name, path.is_dir())
+        })
+        .collect())
+}
+
+pub(crate) fn filename_regex(
+    slot: &InstancePackagerDataSlot,
+) -> Result<Option<Regex>, PackagerError> {
+    slot.filename_regex
+        .as_deref()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|source| PackagerError::FilenameRegex {
+                pattern: String::from(pattern),
+                source,
+            })
+        })
+        .transpose()
+}
+
+/// The path relative to a folder with `/` between folders, which regexes & override keys are matched against.
+pub(crate) fn relative_name(path: &Path, base: &Path) -> String {
+    relative_to(path, base)
+        .to_string_lossy()
+        .replace(path::MAIN_SEPARATOR, "/")
+}
+
+/// A regex's named groups & the values they matched.
+pub(crate) type Captures = Vec<(String, String)>;
+
+/// The values of the regex's named groups for this file, empty if it doesn't match.
+pub(crate) fn named_captures(regex: &Regex, name: &str) -> Captures {
+    let Some(captures) = regex.captures(name) else {
+        return vec![];
+    };
+    regex
+        .capture_names()
+        .flatten()
+        .filter_map(|group| {
+            let value = captures.name(group)?;
+            Some((String::from(group), String::from(value.as_str())))
+        })
+        .collect()
+}
+
+/// The instance name a file gives when it names the instance, the `name` capture of the slot's
+/// `filename_regex` if there is one, otherwise the file's stem.
+pub(crate) fn instance_name(
+    path: &Path,
+    folder_path: &Path,
+    slot: &InstancePackagerDataSlot,
+) -> Result<String, PackagerError> {
+    if let Some(regex) = filename_regex(slot)? {
+        let captures = named_captures(&regex, &relative_name(path, folder_path));
+        if let Some((_, name)) = captures.into_iter().find(|(group, _)| group == "name") {
+            return Ok(name);
+        }
+    }
+    let stem = path.file_stem().unwrap_or_default();
+    Ok(path_str(Path::new(stem))?.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, File};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_get_glob_paths_special_folder_names() {
+        let temp_dir = tempdir().unwrap();
+        let mut folders = vec!["Pocket [SD]/Game [!]", "Pocket [SD]/[a]-[z]"];
+        // Windows doesn't allow `*` or `?` in names
+        if cfg!(not(windows)) {
+            folders.extend(["Pocket [SD]/Why?", "Pocket [SD]/What*"]);
+        }
+        for folder in folders {
+            let folder_path = temp_dir.path().join(folder);
+            create_dir_all(folder_path.join("tracks [a]")).unwrap();
+            File::create(folder_path.join("game.bin")).unwrap();
+            File::create(folder_path.join("tracks [a]/track 1.bin")).unwrap();
+            File::create(folder_path.join(".hidden.bin")).unwrap();
+
+            let names = |glob| -> Vec<String> {
+                get_glob_paths(&folder_path, glob, NameMatching::default())
+                    .unwrap()
+                    .iter()
+                    .map(|path| relative_name(path, &folder_path))
+                    .collect()
+            };
+            assert_eq!(names("*.bin"), vec!["game.bin"]);
+            assert_eq!(names("*/*.bin"), vec!["tracks [a]/track 1.bin"]);
+            assert_eq!(
+                names("**/*.bin"),
+                vec!["game.bin", "tracks [a]/track 1.bin"]
+            );
+            assert_eq!(names("tracks [[]a]/*"), vec!["tracks [a]/track 1.bin"]);
+        }
+    }
+}
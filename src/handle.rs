@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::{BuildOptions, CoreSummary, PackagerError, Reporter};
+
+/// Shared flag for stopping a build from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// How far through scanning a build is.
+///
+/// The total is an estimate as it only grows once each core's asset folder has been walked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub folders_scanned: usize,
+    pub estimated_total: usize,
+}
+
+/// The cancellation token & progress counters threaded through a build.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BuildControl {
+    pub(crate) token: CancellationToken,
+    folders_scanned: Arc<AtomicUsize>,
+    estimated_total: Arc<AtomicUsize>,
+}
+
+impl BuildControl {
+    pub(crate) fn check(&self) -> Result<(), PackagerError> {
+        if self.token.is_cancelled() {
+            Err(PackagerError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn add_to_total(&self, folders: usize) {
+        self.estimated_total.fetch_add(folders, Ordering::SeqCst);
+    }
+
+    pub(crate) fn folder_scanned(&self) {
+        self.folders_scanned.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn progress(&self) -> Progress {
+        Progress {
+            folders_scanned: self.folders_scanned.load(Ordering::SeqCst),
+            estimated_total: self.estimated_total.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// A build running on its own thread, made with `spawn_build`.
+pub struct BuildHandle {
+    control: BuildControl,
+    thread: JoinHandle<Vec<Result<CoreSummary, PackagerError>>>,
+}
+
+impl BuildHandle {
+    pub fn progress(&self) -> Progress {
+        self.control.progress()
+    }
+
+    /// Stops the build at the next folder or instance file, files already written are kept.
+    pub fn cancel(&self) {
+        self.control.token.cancel();
+    }
+
+    /// A clone of the token, for cancelling from somewhere that doesn't own the handle.
+    pub fn token(&self) -> CancellationToken {
+        self.control.token.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the build to finish, with a result per core in the order they were given.
+    ///
+    /// Cores which were stopped part way through, or never started, give `PackagerError::Cancelled`.
+    pub fn join(self) -> Vec<Result<CoreSummary, PackagerError>> {
+        self.thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+/// Runs `build_jsons_for_cores` on a new thread, returning straight away with a handle to it.
+pub fn spawn_build(
+    root_path: PathBuf,
+    core_names: Vec<String>,
+    options: BuildOptions,
+    reporter: impl Reporter + Send + 'static,
+) -> BuildHandle {
+    let control = BuildControl::default();
+    let thread_control = control.clone();
+    let thread = thread::spawn(move || {
+        crate::build_cores(
+            &root_path,
+            &core_names,
+            &options,
+            &thread_control,
+            &reporter,
+        )
+    });
+
+    BuildHandle { control, thread }
+}
@@ -1,29 +1,55 @@
-use std::fs::create_dir_all;
-use std::io::ErrorKind;
-use std::path::{self, Path};
-use std::{error, fs};
-use std::{io, path::PathBuf};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{self, Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
+mod collisions;
+mod cue;
+mod error;
+mod exclude;
 mod glob_stuff;
+mod handle;
+mod manifest;
+mod memory_writes;
+mod multi_disc;
+mod override_keys;
+mod parallel;
+mod per_file;
+mod report;
+mod sorting;
+mod spanned_json;
+mod template;
+mod validate;
+
+pub use collisions::CollisionStrategy;
+pub use error::PackagerError;
+pub use exclude::NO_INSTANCE_MARKER;
+pub use handle::{spawn_build, BuildHandle, CancellationToken, Progress};
+pub use multi_disc::Disc;
+pub use report::{CoreSummary, Event, FolderWarning, RejectReason, Reporter, WriteKind};
+pub use validate::{
+    validate_config, validate_core_config, Severity, ValidationIssue, CONFIG_SCHEMA,
+};
 
 pub static PACKAGER_NAME: &str = "instance-packager.json";
 
-pub fn find_cores_with_package_json(
-    root_path: &PathBuf,
-) -> Result<Vec<String>, Box<dyn error::Error>> {
+pub fn find_cores_with_package_json(root_path: &Path) -> Result<Vec<String>, PackagerError> {
     let cores_path = root_path.join("Cores");
     if !cores_path.exists() {
-        return Err(io::Error::new(ErrorKind::NotFound, "Unable to find Cores/ folder").into());
+        return Err(PackagerError::MissingCoresFolder(root_path.to_path_buf()));
     }
-    let paths = fs::read_dir(cores_path).unwrap();
+    let paths =
+        fs::read_dir(&cores_path).map_err(|source| PackagerError::UnreadableCoresFolder {
+            path: cores_path.clone(),
+            source,
+        })?;
     let mut found_cores: Vec<String> = vec![];
 
     for path in paths.filter_map(|x| x.ok()) {
         let core_path = path.path();
         if core_path.join(PACKAGER_NAME).exists() {
-            let core_name = core_path.file_name().and_then(|f| f.to_str()).unwrap();
-            found_cores.push(String::from(core_name));
+            let core_name = path_str(Path::new(&path.file_name()))?.to_owned();
+            found_cores.push(core_name);
         }
     }
     Ok(found_cores)
@@ -31,148 +57,875 @@ pub fn find_cores_with_package_json(
 
 mod serde_structs;
 
+pub use serde_structs::{
+    ComputedData, CoreSelect, DataSlot, InstanceJSON, InstanceJSONInstance,
+    InstancePackagerDataSlot, InstancePackagerMemoryWrite, InstancePackagerOverrides,
+    InstancePackagerSlotLimit, MemoryData, MemoryValue, MemoryWrite, MergeMode, MultiDisc,
+    OverrideMerge, PackagerConfig, Sort, VariantSelect,
+};
+
+/// An instance file that `plan_core` has decided to build, without anything having been written yet.
+#[derive(Debug)]
+pub struct PlannedInstance {
+    /// The game folder the instance was built from
+    pub source_folder: PathBuf,
+    /// The file matched by the `primary` slot, when the folder makes one instance per file
+    pub primary_file: Option<PathBuf>,
+    /// Where the instance file will be written
+    pub output_path: PathBuf,
+    pub instance_json: InstanceJSON,
+    /// The key of the entry in `overrides` used for this folder, if there was one
+    pub applied_override: Option<String>,
+    /// The `slot_limit` message if the instance has too many slots, these won't be written
+    pub slot_limit_exceeded: Option<String>,
+    /// Set when another instance has the same output path & the strategy is `CollisionStrategy::Error`,
+    /// these won't be written
+    pub output_collision: bool,
+    /// Anything odd found in the folder which didn't stop the instance being built
+    pub warnings: Vec<FolderWarning>,
+    /// Set for a disc of a multi-disc game when `multi_disc` is configured
+    pub disc: Option<Disc>,
+}
+
+impl PlannedInstance {
+    pub fn will_write(&self) -> bool {
+        self.slot_limit_exceeded.is_none() && !self.output_collision
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Mirror the folder structure of the game folders in the output folder
+    pub keep_file_tree: bool,
+    /// Remove instance files made by earlier runs which wouldn't be made any more
+    pub clean_orphans: bool,
+    /// What to do when more than one game would be written to the same instance file
+    pub collision_strategy: CollisionStrategy,
+}
+
 pub fn build_jsons_for_core(
-    root_path: &PathBuf,
+    root_path: &Path,
     core_name: &str,
-    keep_file_tree: bool,
-    on_json: impl Fn(&str) -> (),
-    on_warn: impl Fn(&str, &str) -> (),
-) -> Result<(), Box<dyn error::Error>> {
-    let file_name = root_path.join("Cores").join(core_name).join(PACKAGER_NAME);
-    let data =
-        fs::read_to_string(&file_name).expect(&format!("Unable to read file {:?}", &file_name));
-    let instance_packager: serde_structs::InstancePackager = serde_json::from_str(&data).unwrap();
-
-    let asset_folder = root_path
-        .join("Assets")
-        .join(&instance_packager.platform_id)
-        .join("common");
-
-    fn is_hidden(entry: &DirEntry) -> bool {
-        entry
-            .file_name()
-            .to_str()
-            .map(|s| s.starts_with("."))
-            .unwrap_or(false)
-    }
+    options: &BuildOptions,
+    reporter: &impl Reporter,
+) -> Result<CoreSummary, PackagerError> {
+    let control = handle::BuildControl::default();
+    build_core(
+        root_path,
+        core_name,
+        scan_core(root_path, core_name, options, &control),
+        options,
+        &mut collisions::TakenPaths::new(),
+        &control,
+        reporter,
+    )
+}
 
-    let walker = WalkDir::new(&asset_folder).into_iter();
-    for entry in walker
-        .filter_entry(|e| !is_hidden(e))
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
+/// Builds each of the cores in turn, one core's failure doesn't stop the others.
+///
+/// Cores which share an output folder are checked against each other, the earlier core in the list
+/// keeps its file names if there's a collision.
+/// With the `parallel` feature all the cores are scanned at once before any are written, otherwise each
+/// core is scanned & written before the next is started.
+pub fn build_jsons_for_cores(
+    root_path: &Path,
+    core_names: &[String],
+    options: &BuildOptions,
+    reporter: &impl Reporter,
+) -> Vec<Result<CoreSummary, PackagerError>> {
+    build_cores(
+        root_path,
+        core_names,
+        options,
+        &handle::BuildControl::default(),
+        reporter,
+    )
+}
 
-        if path.is_dir() {
-            let folder_name = path.file_name().and_then(|f| f.to_str()).unwrap();
-            let slots = instance_packager.get_slots(&folder_name);
-            let matches = check_if_dir_matches_slots(&slots, &path).unwrap();
+fn build_cores(
+    root_path: &Path,
+    core_names: &[String],
+    options: &BuildOptions,
+    control: &handle::BuildControl,
+    reporter: &impl Reporter,
+) -> Vec<Result<CoreSummary, PackagerError>> {
+    let scan = |core_name: &String| scan_core(root_path, core_name, options, control);
+    // without the `parallel` feature each core is scanned just before it's written
+    #[cfg(feature = "parallel")]
+    let scans = parallel::map_in_order(core_names, scan);
+    #[cfg(not(feature = "parallel"))]
+    let scans = core_names.iter().map(scan);
 
-            if !matches {
-                continue;
-            }
+    let mut taken = collisions::TakenPaths::new();
+    core_names
+        .iter()
+        .zip(scans)
+        .map(|(core_name, scanned)| {
+            build_core(
+                root_path, core_name, scanned, options, &mut taken, control, reporter,
+            )
+        })
+        .collect()
+}
+
+fn build_core(
+    root_path: &Path,
+    core_name: &str,
+    scanned: Result<ScannedCore, PackagerError>,
+    options: &BuildOptions,
+    taken: &mut collisions::TakenPaths,
+    control: &handle::BuildControl,
+    reporter: &impl Reporter,
+) -> Result<CoreSummary, PackagerError> {
+    let tally = report::Tally::new(reporter);
+    tally.report(Event::CoreStarted {
+        core_name: String::from(core_name),
+    });
+
+    let scanned = scanned?;
+    let output_folder = root_path.join(&scanned.config.output);
+    let plan = plan_from_scan(root_path, core_name, scanned, options, taken, &tally);
+    // a folder that couldn't be scanned is missing from the plan, so its instance file would look orphaned
+    let scan_failed = tally.summary().io_errors > 0;
+    let written = write_plan_until_cancelled(root_path, &plan, control, &tally);
+    taken.extend(plan.iter().filter(|p| p.will_write()).map(|p| {
+        (
+            collisions::collision_key(&p.output_path),
+            p.source_folder.clone(),
+        )
+    }));
+    // the manifest is still updated after a cancel so the files that were written can be cleaned up later
+    manifest::update(
+        root_path,
+        &output_folder,
+        core_name,
+        &plan,
+        options.clean_orphans && !scan_failed && written.is_ok(),
+        &tally,
+    )?;
+    written?;
+
+    let summary = tally.summary();
+    tally.report(Event::CoreFinished {
+        core_name: String::from(core_name),
+        summary: summary.clone(),
+    });
+    Ok(summary)
+}
+
+fn load_core_config(root_path: &Path, core_name: &str) -> Result<PackagerConfig, PackagerError> {
+    PackagerConfig::load(&root_path.join("Cores").join(core_name).join(PACKAGER_NAME))
+}
+
+pub(crate) fn is_hidden(entry: &DirEntry) -> bool {
+    entry
+        .file_name()
+        .to_str()
+        .map(|s| s.starts_with('.'))
+        .unwrap_or(false)
+}
+
+/// Works out every instance file that would be built for a core, without touching the disk.
+pub fn plan_core(
+    root_path: &Path,
+    core_name: &str,
+    options: &BuildOptions,
+    reporter: &impl Reporter,
+) -> Result<Vec<PlannedInstance>, PackagerError> {
+    let scanned = scan_core(
+        root_path,
+        core_name,
+        options,
+        &handle::BuildControl::default(),
+    )?;
+    Ok(plan_from_scan(
+        root_path,
+        core_name,
+        scanned,
+        options,
+        &collisions::TakenPaths::new(),
+        reporter,
+    ))
+}
+
+/// Everything found in a core's asset folder, in walk order, before any of it has been reported.
+struct ScannedCore {
+    config: PackagerConfig,
+    folders: Vec<ScannedFolder>,
+}
+
+enum ScannedFolder {
+    /// The folder couldn't be walked into, or a file one of its games depends on couldn't be read
+    IoError { path: PathBuf, message: String },
+    Scanned {
+        folder: PathBuf,
+        games: Vec<ScannedGame>,
+    },
+}
+
+/// A folder, or a file in a folder in one-instance-per-file mode.
+enum ScannedGame {
+    Rejected { path: PathBuf, reason: RejectReason },
+    Planned(Box<PlannedInstance>),
+}
+
+fn scan_core(
+    root_path: &Path,
+    core_name: &str,
+    options: &BuildOptions,
+    control: &handle::BuildControl,
+) -> Result<ScannedCore, PackagerError> {
+    control.check()?;
+    let config = load_core_config(root_path, core_name)?;
+    let asset_folder = config.asset_folder(root_path);
+    let excludes = config.excludes()?;
+    let excluded_folder = |entry: &DirEntry| {
+        entry.path().is_dir()
+            && (exclude::has_marker(entry.path())
+                || entry.depth() > 0
+                    && excludes.excludes(
+                        &glob_stuff::relative_name(entry.path(), &asset_folder),
+                        true,
+                    ))
+    };
 
-            let mut instance_json = build_json(&path, &instance_packager)?;
-            let output_path = root_path.join(&instance_packager.output);
+    // walking is cheap compared to the globbing, so only checking the folders is spread out
+    let walked: Vec<Result<PathBuf, walkdir::Error>> = WalkDir::new(&asset_folder)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| !is_hidden(e) && !excluded_folder(e))
+        .filter(|entry| entry.as_ref().map(|e| e.path().is_dir()).unwrap_or(true))
+        .map(|entry| entry.map(|e| e.into_path()))
+        .collect();
+    control.add_to_total(walked.len());
 
-            instance_json.instance.data_path = format!(
-                "{}/",
-                path.strip_prefix(&asset_folder)?
-                    .to_str()
-                    .unwrap()
-                    .replace(path::MAIN_SEPARATOR, "/")
-            );
+    let context = ScanContext {
+        root_path,
+        asset_folder: &asset_folder,
+        config: &config,
+        excludes: &excludes,
+        options,
+    };
+    let folders = parallel::map_in_order(&walked, |entry| {
+        control.check()?;
+        let scanned = match entry {
+            Ok(path) => match scan_folder(&context, path) {
+                // one unreadable file only costs its own folder
+                Err(PackagerError::UnreadableFile { path, source }) => Ok(ScannedFolder::IoError {
+                    path,
+                    message: source.to_string(),
+                }),
+                scanned => scanned,
+            },
+            Err(err) => Ok(ScannedFolder::IoError {
+                path: err.path().unwrap_or(&asset_folder).to_path_buf(),
+                message: err.to_string(),
+            }),
+        };
+        control.folder_scanned();
+        scanned
+    })
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
 
-            let file_name = instance_packager.get_filename(&path)?;
-            let file_name = format!("{}.json", file_name);
+    Ok(ScannedCore { config, folders })
+}
 
-            fs::create_dir_all(&output_path)?;
+/// What's shared by every folder scanned for a core.
+struct ScanContext<'a> {
+    root_path: &'a Path,
+    asset_folder: &'a Path,
+    config: &'a PackagerConfig,
+    /// The config's `exclude` globs, which files are checked against as well as folders
+    excludes: &'a exclude::Excludes,
+    options: &'a BuildOptions,
+}
 
-            let file_path = if keep_file_tree {
-                output_path
-                    .join(&path.parent().unwrap().strip_prefix(&asset_folder)?)
-                    .join(&file_name)
+fn scan_folder(context: &ScanContext, path: &Path) -> Result<ScannedFolder, PackagerError> {
+    let folder_name = folder_name(path)?;
+    let folder = glob_stuff::relative_name(path, context.asset_folder);
+    let slots = context.config.get_slots(&folder);
+    let slot_matches = match_slots(
+        path,
+        &folder,
+        &slots,
+        context.config.name_matching(),
+        context.excludes,
+    )?;
+    let scan_game =
+        |primary_file: Option<&Path>, disc: Option<Disc>, slot_matches: &[Vec<PathBuf>]| {
+            scan_game(context, path, primary_file, disc, &slots, slot_matches)
+        };
+    let find_discs = context.config.multi_disc.is_some();
+    let playlist = if find_discs {
+        multi_disc::read_playlist(path)?
+    } else {
+        None
+    };
+
+    let games = match (per_file::primary_slot(&slots), playlist) {
+        (None, Some(playlist)) => {
+            let playlist_name = relative_to(&playlist.path, path).to_string_lossy();
+            let entries: Vec<PathBuf> = playlist.entries.iter().map(|e| path.join(e)).collect();
+            match entries.iter().position(|entry| !entry.exists()) {
+                Some(missing) => vec![ScannedGame::Rejected {
+                    path: playlist.path.clone(),
+                    reason: RejectReason::PlaylistMissingFile {
+                        playlist: playlist_name.into_owned(),
+                        file: playlist.entries[missing].clone(),
+                    },
+                }],
+                None => per_file::group_by_primary(path, &slots, &slot_matches, &entries, None)?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, (primary_file, game_matches))| {
+                        let disc = Disc {
+                            game: per_file::stem(&playlist.path),
+                            number: index + 1,
+                            group_folder: path.to_path_buf(),
+                            slot_matches: vec![],
+                        };
+                        scan_game(Some(&primary_file), Some(disc), &game_matches)
+                    })
+                    .collect::<Result<_, _>>()?,
+            }
+        }
+        (None, None) => {
+            let disc = path
+                .parent()
+                .filter(|_| find_discs)
+                .and_then(|parent| multi_disc::disc_from_name(folder_name, parent));
+            vec![scan_game(None, disc, &slot_matches)?]
+        }
+        (Some(primary_index), _) if slot_matches[primary_index].is_empty() => {
+            let primary = &slots[primary_index];
+            if primary.required {
+                vec![ScannedGame::Rejected {
+                    path: path.to_path_buf(),
+                    reason: RejectReason::MissingRequiredSlot {
+                        slot_id: primary.id,
+                        filename: primary.filename.clone(),
+                    },
+                }]
             } else {
-                output_path.join(&file_name)
+                vec![]
+            }
+        }
+        (Some(primary_index), _) => {
+            let primaries = &slot_matches[primary_index];
+            per_file::group_by_primary(path, &slots, &slot_matches, primaries, Some(primary_index))?
+                .into_iter()
+                .map(|(primary_file, game_matches)| {
+                    let disc = find_discs
+                        .then(|| multi_disc::disc_from_name(&per_file::stem(&primary_file), path))
+                        .flatten();
+                    scan_game(Some(&primary_file), disc, &game_matches)
+                })
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    Ok(ScannedFolder::Scanned {
+        folder: path.to_path_buf(),
+        games,
+    })
+}
+
+fn scan_game(
+    context: &ScanContext,
+    path: &Path,
+    primary_file: Option<&Path>,
+    disc: Option<Disc>,
+    slots: &[InstancePackagerDataSlot],
+    slot_matches: &[Vec<PathBuf>],
+) -> Result<ScannedGame, PackagerError> {
+    let ScanContext {
+        root_path,
+        asset_folder,
+        config: instance_packager,
+        options,
+        ..
+    } = context;
+    // what override keys are matched against
+    let folder = glob_stuff::relative_name(path, asset_folder);
+
+    if let Some(reason) = check_slot_matches(slots, slot_matches, path)? {
+        return Ok(ScannedGame::Rejected {
+            path: primary_file.unwrap_or(path).to_path_buf(),
+            reason,
+        });
+    }
+
+    let (mut instance_json, mut warnings) =
+        build_json(path, &folder, slots, slot_matches, instance_packager)?;
+    warnings.extend(case_only_differences(path, slot_matches));
+
+    instance_json.instance.data_path = format!(
+        "{}/",
+        path_str(relative_to(path, asset_folder))?.replace(path::MAIN_SEPARATOR, "/")
+    );
+
+    // an override's fixed `filename` beats a template, but can't name every game in a flat folder
+    let fixed_name = primary_file.is_none()
+        && instance_packager
+            .get_override(&folder)
+            .is_some_and(|(_, o)| o.filename.is_some());
+    let template_name = match instance_packager.get_filename_template(&folder) {
+        Some(template) if !fixed_name => {
+            let sources = template::Sources {
+                folder_path: path,
+                slots,
+                slot_matches,
+                data_slots: &instance_json.instance.data_slots,
             };
+            Some(template::render(template, &sources)?).filter(|name| !name.is_empty())
+        }
+        _ => None,
+    };
+
+    let file_name = match (template_name, primary_file, per_file::primary_slot(slots)) {
+        (Some(template_name), _, _) => template_name,
+        (None, Some(primary_file), Some(primary_index)) => {
+            glob_stuff::instance_name(primary_file, path, &slots[primary_index])?
+        }
+        // a disc listed in a playlist
+        (None, Some(primary_file), None) => per_file::stem(primary_file),
+        (None, None, _) => {
+            instance_packager.filename_from_matches(path, &folder, slots, slot_matches)?
+        }
+    };
+    let file_name = format!("{}.json", file_name);
+
+    let output_path = root_path.join(&instance_packager.output);
+    let file_path = match (options.keep_file_tree, path.parent()) {
+        (true, Some(parent)) => output_path
+            .join(relative_to(parent, asset_folder))
+            .join(&file_name),
+        _ => output_path.join(&file_name),
+    };
 
-            if let Some(slot_limit) = &instance_packager.slot_limit {
-                if instance_json.instance.data_slots.len() > slot_limit.count {
-                    on_warn(
-                        &file_path.strip_prefix(root_path)?.to_str().unwrap(),
-                        &slot_limit.message,
-                    );
-                    continue;
+    let slot_limit_exceeded = instance_packager
+        .slot_limit
+        .as_ref()
+        .filter(|slot_limit| instance_json.instance.data_slots.len() > slot_limit.count)
+        .map(|slot_limit| slot_limit.message.clone());
+
+    Ok(ScannedGame::Planned(Box::new(PlannedInstance {
+        source_folder: path.to_path_buf(),
+        primary_file: primary_file.map(Path::to_path_buf),
+        output_path: file_path,
+        instance_json,
+        applied_override: instance_packager
+            .get_override(&folder)
+            .map(|(key, _)| String::from(key)),
+        slot_limit_exceeded,
+        output_collision: false,
+        warnings,
+        disc: disc.map(|disc| Disc {
+            slot_matches: slots
+                .iter()
+                .map(|slot| slot.id)
+                .zip(slot_matches.iter().cloned())
+                .collect(),
+            ..disc
+        }),
+    })))
+}
+
+/// Reports what the scan found, in order, & resolves any output collisions.
+fn plan_from_scan(
+    root_path: &Path,
+    core_name: &str,
+    scanned: ScannedCore,
+    options: &BuildOptions,
+    taken: &collisions::TakenPaths,
+    reporter: &impl Reporter,
+) -> Vec<PlannedInstance> {
+    let mut plan = vec![];
+    for folder in scanned.folders {
+        match folder {
+            ScannedFolder::IoError { path, message } => reporter.report(Event::IoError {
+                path: relative_to(&path, root_path).to_path_buf(),
+                message,
+            }),
+            ScannedFolder::Scanned { folder, games } => {
+                reporter.report(Event::FolderScanned {
+                    folder: relative_to(&folder, root_path).to_path_buf(),
+                });
+                for game in games {
+                    report_game(root_path, game, &mut plan, reporter);
                 }
             }
-            create_dir_all(&file_path.parent().unwrap())?;
-            std::fs::write(
-                &file_path,
-                serde_json::to_string_pretty(&instance_json).unwrap(),
-            )?;
-            on_json(&file_path.strip_prefix(root_path)?.to_str().unwrap());
         }
     }
 
+    if let Some(multi_disc) = &scanned.config.multi_disc {
+        plan = multi_disc::group_discs(plan, multi_disc, &scanned.config, root_path, reporter);
+    }
+
+    // files another core sharing the output folder made on an earlier run count as taken too
+    let output_folder = root_path.join(&scanned.config.output);
+    let mut taken = taken.clone();
+    for (other_core, file_path) in manifest::other_cores_files(&output_folder, core_name) {
+        taken
+            .entry(collisions::collision_key(&file_path))
+            .or_insert_with(|| root_path.join("Cores").join(other_core));
+    }
+
+    collisions::resolve_collisions(
+        root_path,
+        &mut plan,
+        &taken,
+        options.collision_strategy,
+        reporter,
+    );
+    plan
+}
+
+fn report_game(
+    root_path: &Path,
+    game: ScannedGame,
+    plan: &mut Vec<PlannedInstance>,
+    reporter: &impl Reporter,
+) {
+    match game {
+        ScannedGame::Rejected { path, reason } => reporter.report(Event::FolderRejected {
+            folder: relative_to(&path, root_path).to_path_buf(),
+            reason,
+        }),
+        ScannedGame::Planned(planned) => {
+            let game_path = planned
+                .primary_file
+                .as_ref()
+                .unwrap_or(&planned.source_folder);
+            let folder = relative_to(game_path, root_path).to_path_buf();
+            for warning in &planned.warnings {
+                reporter.report(Event::FolderWarning {
+                    folder: folder.clone(),
+                    warning: warning.clone(),
+                });
+            }
+            plan.push(*planned);
+        }
+    }
+}
+
+/// Writes out the instance files from a plan made by `plan_core`, skipping any over the slot limit
+/// or with colliding output paths.
+///
+/// A file that fails to write is reported as an `Event::IoError` and the rest of the plan carries on.
+pub fn write_plan(root_path: &Path, plan: &[PlannedInstance], reporter: &impl Reporter) {
+    // can't fail without a token to cancel it
+    let _ = write_plan_until_cancelled(root_path, plan, &handle::BuildControl::default(), reporter);
+}
+
+fn write_plan_until_cancelled(
+    root_path: &Path,
+    plan: &[PlannedInstance],
+    control: &handle::BuildControl,
+    reporter: &impl Reporter,
+) -> Result<(), PackagerError> {
+    for planned in plan {
+        control.check()?;
+        let path = relative_to(&planned.output_path, root_path).to_path_buf();
+
+        if let Some(message) = &planned.slot_limit_exceeded {
+            reporter.report(Event::SlotLimitExceeded {
+                path,
+                message: message.clone(),
+            });
+            continue;
+        }
+
+        if planned.output_collision {
+            continue;
+        }
+
+        match write_instance_json(&planned.output_path, &planned.instance_json) {
+            Ok(Some(kind)) => reporter.report(Event::InstanceWritten { path, kind }),
+            Ok(None) => reporter.report(Event::InstanceUnchanged { path }),
+            Err(err) => reporter.report(Event::IoError {
+                path,
+                message: err.to_string(),
+            }),
+        }
+    }
     Ok(())
 }
 
+/// The instance files made by earlier runs for this core which the plan wouldn't make again.
+///
+/// Only files recorded in the core's manifest are considered, so hand written instance files are never included.
+pub fn find_orphans(
+    root_path: &Path,
+    core_name: &str,
+    plan: &[PlannedInstance],
+) -> Result<Vec<PathBuf>, PackagerError> {
+    let instance_packager = load_core_config(root_path, core_name)?;
+    Ok(manifest::find_orphans(
+        &root_path.join(&instance_packager.output),
+        core_name,
+        plan,
+    ))
+}
+
+/// Removes the files `find_orphans` returns & records the plan in the core's manifest.
+pub fn clean_orphans(
+    root_path: &Path,
+    core_name: &str,
+    plan: &[PlannedInstance],
+    reporter: &impl Reporter,
+) -> Result<(), PackagerError> {
+    let instance_packager = load_core_config(root_path, core_name)?;
+    manifest::update(
+        root_path,
+        &root_path.join(&instance_packager.output),
+        core_name,
+        plan,
+        true,
+        reporter,
+    )
+}
+
+/// Writes the instance file, unless one with the exact same contents is already there.
+///
+/// Returns `None` when the existing file was left alone.
+fn write_instance_json(
+    file_path: &Path,
+    instance_json: &InstanceJSON,
+) -> Result<Option<WriteKind>, PackagerError> {
+    let write_error = |source| PackagerError::Write {
+        path: file_path.to_path_buf(),
+        source,
+    };
+    let contents = serde_json::to_string_pretty(instance_json)
+        .map_err(|err| write_error(std::io::Error::from(err)))?;
+
+    let kind = match fs::read(file_path) {
+        Ok(existing) if existing == contents.as_bytes() => return Ok(None),
+        Ok(_) => WriteKind::Updated,
+        Err(_) => WriteKind::New,
+    };
+
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).map_err(write_error)?;
+    }
+
+    // written alongside then renamed over, so a cancelled or interrupted build never leaves half a file
+    let temp_path = file_path.with_file_name(format!(
+        ".{}.tmp",
+        file_path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    fs::write(&temp_path, contents).map_err(write_error)?;
+    if let Err(err) = fs::rename(&temp_path, file_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(write_error(err));
+    }
+    Ok(Some(kind))
+}
+
+/// The files each slot's glob matches in a folder, in the same order as the slots, less the ones the
+/// config's `exclude` leaves out.
+///
+/// `folder` is the game folder's path relative to the asset folder, which the excludes are matched against.
+fn match_slots(
+    folder_path: &Path,
+    folder: &str,
+    slots: &[InstancePackagerDataSlot],
+    matching: glob_stuff::NameMatching,
+    excludes: &exclude::Excludes,
+) -> Result<Vec<Vec<PathBuf>>, PackagerError> {
+    slots
+        .iter()
+        .map(|slot| {
+            Ok(glob_stuff::get_slot_paths(folder_path, slot, matching)?
+                .into_iter()
+                .filter(|file| {
+                    let name = glob_stuff::relative_name(file, folder_path);
+                    let relative = match folder {
+                        "" => name,
+                        folder => format!("{folder}/{name}"),
+                    };
+                    !excludes.excludes(&relative, file.is_dir())
+                })
+                .collect())
+        })
+        .collect()
+}
+
 fn build_json(
     folder_path: &Path,
-    instance_packager: &serde_structs::InstancePackager,
-) -> Result<serde_structs::InstanceJSON, Box<dyn error::Error>> {
-    let folder_name = folder_path.file_name().and_then(|f| f.to_str()).unwrap();
-    let slots = &instance_packager.get_slots(folder_name);
-    let mut instance_json = serde_structs::InstanceJSON::new();
+    folder: &str,
+    slots: &[InstancePackagerDataSlot],
+    slot_matches: &[Vec<PathBuf>],
+    instance_packager: &PackagerConfig,
+) -> Result<(InstanceJSON, Vec<FolderWarning>), PackagerError> {
+    let mut instance_json = InstanceJSON::new();
+    let mut warnings = vec![];
 
-    for slot in slots {
-        let partial_glob = slot.filename.clone();
-        let full_glob = String::from(folder_path.join(partial_glob).to_str().unwrap());
-        let paths: Vec<PathBuf> = glob_stuff::get_glob_paths(&full_glob)?;
+    for (slot_index, slot) in slots.iter().enumerate() {
+        let paths = slot_matches[slot_index].clone();
 
         let sorted_paths = match slot.sort {
-            serde_structs::Sort::Single => paths,
-            serde_structs::Sort::Ascending => paths,
-            serde_structs::Sort::Descending => paths.into_iter().rev().collect(),
+            Sort::Cue => match cue::order_by_cue(folder_path, slots, slot_matches, slot_index)? {
+                cue::CueOrder::Ordered {
+                    paths,
+                    warnings: cue_warnings,
+                } => {
+                    warnings.extend(cue_warnings);
+                    paths
+                }
+                // `check_slot_matches` has already turned these folders away
+                cue::CueOrder::Rejected(_) => vec![],
+            },
+            _ => sorting::sort_paths(paths, folder_path, slot)?,
         };
 
         for (index, path) in sorted_paths.iter().enumerate() {
-            instance_json
-                .instance
-                .data_slots
-                .push(serde_structs::SlotsCoresAndWrites::DataSlot {
-                    id: (slot.id + index).into(),
-                    filename: String::from(
-                        path.strip_prefix(folder_path).unwrap().to_str().unwrap(),
-                    ),
-                })
+            instance_json.instance.data_slots.push(DataSlot {
+                id: slot.file_id(index).expect(
+                    "`check_slot_matches` has already turned away folders with ids too big",
+                ),
+                filename: path_str(relative_to(path, folder_path))?.to_owned(),
+            })
         }
     }
 
-    instance_json.instance.memory_writes = instance_packager.get_memory_writes(&folder_name);
-    instance_json.instance.core_select = instance_packager.get_core_select(&folder_name);
-    Ok(instance_json)
+    let sources = template::Sources {
+        folder_path,
+        slots,
+        slot_matches,
+        data_slots: &instance_json.instance.data_slots,
+    };
+    let id_offset = match instance_packager.multi_disc {
+        Some(MultiDisc::Combined { id_offset }) => Some(id_offset),
+        _ => None,
+    };
+    let (memory_writes, write_warnings) = memory_writes::evaluate(
+        &instance_packager.get_memory_writes(folder),
+        folder,
+        &sources,
+        instance_packager.name_matching(),
+        id_offset,
+    )?;
+    instance_json.instance.memory_writes = memory_writes;
+    warnings.extend(write_warnings);
+    instance_json.instance.core_select = instance_packager.get_core_select(folder);
+    instance_json.instance.variant_select = instance_packager.get_variant_select(folder);
+    Ok((instance_json, warnings))
 }
 
-fn check_if_dir_matches_slots(
-    data_slots: &Vec<serde_structs::InstancePackagerDataSlot>,
+/// Returns the reason the folder doesn't have the files the required slots need, if it doesn't.
+///
+/// Also turns the folder away when a slot has more files than its `max_count`, or when a multi-file
+/// slot's ids would run into another slot's.
+fn check_slot_matches(
+    data_slots: &[InstancePackagerDataSlot],
+    slot_matches: &[Vec<PathBuf>],
     path: &Path,
-) -> Result<bool, Box<dyn error::Error>> {
-    for slot in data_slots {
+) -> Result<Option<RejectReason>, PackagerError> {
+    // which slot each id has been given to
+    let mut taken_ids: HashMap<usize, usize> = HashMap::new();
+
+    for (slot_index, slot) in data_slots.iter().enumerate() {
+        let paths = &slot_matches[slot_index];
+
         if slot.required {
-            let partial_glob = slot.filename.clone();
-            let full_glob = String::from(path.join(partial_glob).to_str().unwrap());
-            let paths: Vec<PathBuf> = glob_stuff::get_glob_paths(&full_glob)?;
-
-            if paths.len() == 0
-                || (matches!(slot.sort, serde_structs::Sort::Single) && paths.len() > 1)
-            {
-                return Ok(false);
+            if paths.is_empty() {
+                return Ok(Some(RejectReason::MissingRequiredSlot {
+                    slot_id: slot.id,
+                    filename: slot.filename.clone(),
+                }));
+            }
+
+            if matches!(slot.sort, Sort::Single) && paths.len() > 1 {
+                return Ok(Some(RejectReason::TooManyForSingleSlot {
+                    slot_id: slot.id,
+                    filename: slot.filename.clone(),
+                    count: paths.len(),
+                }));
+            }
+        }
+
+        // cue slots only take the files their cue sheet mentions
+        let count = if slot.sort == Sort::Cue {
+            match cue::order_by_cue(path, data_slots, slot_matches, slot_index)? {
+                cue::CueOrder::Ordered { paths, .. } => paths.len(),
+                cue::CueOrder::Rejected(reason) => return Ok(Some(reason)),
             }
+        } else {
+            paths.len()
+        };
+
+        if let Some(max_count) = slot.max_count.filter(|max_count| count > *max_count) {
+            return Ok(Some(RejectReason::TooManyFiles {
+                slot_id: slot.id,
+                filename: slot.filename.clone(),
+                count,
+                max_count,
+            }));
+        }
+
+        for index in 0..count {
+            let Some(id) = slot.file_id(index) else {
+                return Ok(Some(RejectReason::IdOutOfRange { slot_id: slot.id }));
+            };
+            if let Some(other_slot_id) = taken_ids.insert(id, slot.id) {
+                // reported against whichever slot's range reached into the other's
+                let (slot_id, other_slot_id) = if id == slot.id {
+                    (other_slot_id, slot.id)
+                } else {
+                    (slot.id, other_slot_id)
+                };
+                return Ok(Some(RejectReason::SlotIdCollision {
+                    slot_id,
+                    other_slot_id,
+                    id,
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Pairs of matched files whose names only differ by case, exFAT would treat them as the same file.
+fn case_only_differences(folder_path: &Path, slot_matches: &[Vec<PathBuf>]) -> Vec<FolderWarning> {
+    let mut names: Vec<String> = slot_matches
+        .iter()
+        .flatten()
+        .map(|path| glob_stuff::relative_name(path, folder_path))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let mut seen: HashMap<String, &String> = HashMap::new();
+    let mut warnings = vec![];
+    for name in &names {
+        if let Some(other) = seen.insert(name.to_lowercase(), name) {
+            warnings.push(FolderWarning::CaseOnlyDifference {
+                file: other.clone(),
+                other: name.clone(),
+            });
         }
     }
+    warnings
+}
+
+pub(crate) fn path_str(path: &Path) -> Result<&str, PackagerError> {
+    path.to_str()
+        .ok_or_else(|| PackagerError::NonUtf8Path(path.to_path_buf()))
+}
 
-    Ok(true)
+pub(crate) fn folder_name(path: &Path) -> Result<&str, PackagerError> {
+    path.file_name()
+        .and_then(|f| f.to_str())
+        .ok_or_else(|| PackagerError::NonUtf8Path(path.to_path_buf()))
+}
+
+// everything walked / globbed lives under the base, so this only falls back for odd inputs
+pub(crate) fn relative_to<'a>(path: &'a Path, base: &Path) -> &'a Path {
+    path.strip_prefix(base).unwrap_or(path)
 }
 
 #[cfg(test)]
@@ -180,6 +933,23 @@ mod tests {
     use super::*;
     mod test_helpers;
 
+    fn check_if_dir_matches_slots(
+        data_slots: &[InstancePackagerDataSlot],
+        path: &Path,
+    ) -> Result<Option<RejectReason>, PackagerError> {
+        check_slot_matches(
+            data_slots,
+            &match_slots(
+                path,
+                "",
+                data_slots,
+                Default::default(),
+                &Default::default(),
+            )?,
+            path,
+        )
+    }
+
     #[test]
     fn test_find_cores_with_package_json_some() {
         let temp_dir = test_helpers::make_fake_files(vec![
@@ -205,7 +975,58 @@ mod tests {
         ]);
         let path = temp_dir.path();
         let results = find_cores_with_package_json(&PathBuf::from(path)).unwrap();
-        assert!(results.len() == 0);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_find_cores_with_package_json_missing_cores() {
+        let temp_dir = test_helpers::make_fake_files(vec!["Assets/something/common/file.bin"]);
+        let path = temp_dir.path();
+        let result = find_cores_with_package_json(path);
+        assert!(matches!(result, Err(PackagerError::MissingCoresFolder(_))));
+    }
+
+    #[test]
+    fn test_find_cores_with_package_json_unreadable_cores() {
+        let temp_dir = test_helpers::make_fake_files(vec!["Cores"]);
+        let path = temp_dir.path();
+        let result = find_cores_with_package_json(path);
+        assert!(matches!(
+            result,
+            Err(PackagerError::UnreadableCoresFolder { path, .. }) if path == temp_dir.path().join("Cores")
+        ));
+    }
+
+    #[test]
+    fn test_build_jsons_for_core_malformed_config() {
+        let temp_dir =
+            test_helpers::make_fake_files(vec!["Cores/core_name/instance-packager.json"]);
+        let path = temp_dir.path();
+        fs::write(
+            path.join("Cores/core_name/instance-packager.json"),
+            "{\n  \"output\": \"Assets/abc/core_name\",\n  \"platform_id\": 12\n}",
+        )
+        .unwrap();
+
+        let result = build_jsons_for_core(path, "core_name", &BuildOptions::default(), &|_| {});
+
+        match result {
+            Err(PackagerError::ConfigParse { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_build_jsons_for_core_missing_config() {
+        let temp_dir = test_helpers::make_fake_files(vec!["Cores/core_name/core.json"]);
+        let path = temp_dir.path();
+
+        let result = build_jsons_for_core(path, "core_name", &BuildOptions::default(), &|_| {});
+
+        assert!(matches!(
+            result,
+            Err(PackagerError::UnreadableConfig { .. })
+        ));
     }
 
     #[test]
@@ -218,25 +1039,37 @@ mod tests {
         let path = temp_dir.path();
 
         let data_slots = vec![
-            serde_structs::InstancePackagerDataSlot {
+            InstancePackagerDataSlot {
                 id: 101,
                 filename: String::from("*.bin"),
                 required: true,
-                sort: serde_structs::Sort::Single,
+                sort: Sort::Single,
                 as_filename: None,
+                sort_regex: None,
+                filename_regex: None,
+                primary: None,
+                max_count: None,
+                id_stride: None,
+                exclude: None,
             },
-            serde_structs::InstancePackagerDataSlot {
+            InstancePackagerDataSlot {
                 id: 102,
                 filename: String::from("*.cue"),
                 required: true,
-                sort: serde_structs::Sort::Ascending,
+                sort: Sort::Ascending,
                 as_filename: None,
+                sort_regex: None,
+                filename_regex: None,
+                primary: None,
+                max_count: None,
+                id_stride: None,
+                exclude: None,
             },
         ];
 
-        let result = check_if_dir_matches_slots(&data_slots, &path);
+        let result = check_if_dir_matches_slots(&data_slots, path);
 
-        assert!(matches!(result, Ok(true)));
+        assert!(matches!(result, Ok(None)));
     }
 
     #[test]
@@ -250,25 +1083,44 @@ mod tests {
         let path = temp_dir.path();
 
         let data_slots = vec![
-            serde_structs::InstancePackagerDataSlot {
+            InstancePackagerDataSlot {
                 id: 101,
                 filename: String::from("*.bin"),
                 required: true,
-                sort: serde_structs::Sort::Single,
+                sort: Sort::Single,
                 as_filename: None,
+                sort_regex: None,
+                filename_regex: None,
+                primary: None,
+                max_count: None,
+                id_stride: None,
+                exclude: None,
             },
-            serde_structs::InstancePackagerDataSlot {
+            InstancePackagerDataSlot {
                 id: 102,
                 filename: String::from("*.cue"),
                 required: true,
-                sort: serde_structs::Sort::Ascending,
+                sort: Sort::Ascending,
                 as_filename: None,
+                sort_regex: None,
+                filename_regex: None,
+                primary: None,
+                max_count: None,
+                id_stride: None,
+                exclude: None,
             },
         ];
 
-        let result = check_if_dir_matches_slots(&data_slots, &path);
+        let result = check_if_dir_matches_slots(&data_slots, path);
 
-        assert!(matches!(result, Ok(false)));
+        assert_eq!(
+            result.unwrap(),
+            Some(RejectReason::TooManyForSingleSlot {
+                slot_id: 101,
+                filename: String::from("*.bin"),
+                count: 2
+            })
+        );
     }
 
     #[test]
@@ -281,24 +1133,150 @@ mod tests {
         let path = temp_dir.path();
 
         let data_slots = vec![
-            serde_structs::InstancePackagerDataSlot {
+            InstancePackagerDataSlot {
                 id: 101,
                 filename: String::from("*.bin"),
                 required: true,
-                sort: serde_structs::Sort::Single,
+                sort: Sort::Single,
                 as_filename: None,
+                sort_regex: None,
+                filename_regex: None,
+                primary: None,
+                max_count: None,
+                id_stride: None,
+                exclude: None,
             },
-            serde_structs::InstancePackagerDataSlot {
+            InstancePackagerDataSlot {
                 id: 102,
                 filename: String::from("*.cue"),
                 required: true,
-                sort: serde_structs::Sort::Ascending,
+                sort: Sort::Ascending,
                 as_filename: None,
+                sort_regex: None,
+                filename_regex: None,
+                primary: None,
+                max_count: None,
+                id_stride: None,
+                exclude: None,
             },
         ];
 
-        let result = check_if_dir_matches_slots(&data_slots, &path);
+        let result = check_if_dir_matches_slots(&data_slots, path);
+
+        assert_eq!(
+            result.unwrap(),
+            Some(RejectReason::MissingRequiredSlot {
+                slot_id: 101,
+                filename: String::from("*.bin")
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_if_dir_matches_slots_id_ranges() {
+        let temp_dir = test_helpers::make_fake_files(vec![
+            "Game (Track 1).bin",
+            "Game (Track 2).bin",
+            "Game (Track 3).bin",
+            "Game.sav",
+        ]);
+        let path = temp_dir.path();
+
+        let tracks = InstancePackagerDataSlot {
+            id: 101,
+            filename: String::from("*.bin"),
+            required: true,
+            sort: Sort::Ascending,
+            ..Default::default()
+        };
+        let save = InstancePackagerDataSlot {
+            id: 103,
+            filename: String::from("*.sav"),
+            sort: Sort::Single,
+            ..Default::default()
+        };
+
+        let result = check_if_dir_matches_slots(&[tracks.clone(), save.clone()], path);
+        assert_eq!(
+            result.unwrap(),
+            Some(RejectReason::SlotIdCollision {
+                slot_id: 101,
+                other_slot_id: 103,
+                id: 103
+            })
+        );
+
+        // declared the other way round it's still the tracks that ran into the save
+        let result = check_if_dir_matches_slots(&[save.clone(), tracks.clone()], path);
+        assert_eq!(
+            result.unwrap(),
+            Some(RejectReason::SlotIdCollision {
+                slot_id: 101,
+                other_slot_id: 103,
+                id: 103
+            })
+        );
+
+        let strided = InstancePackagerDataSlot {
+            id_stride: Some(10),
+            ..tracks.clone()
+        };
+        let result = check_if_dir_matches_slots(&[strided, save.clone()], path);
+        assert_eq!(result.unwrap(), None);
+
+        let overflowing = InstancePackagerDataSlot {
+            id_stride: Some(usize::MAX / 2),
+            ..tracks.clone()
+        };
+        let result = check_if_dir_matches_slots(&[overflowing, save.clone()], path);
+        assert_eq!(
+            result.unwrap(),
+            Some(RejectReason::IdOutOfRange { slot_id: 101 })
+        );
+
+        let limited = InstancePackagerDataSlot {
+            max_count: Some(2),
+            ..tracks
+        };
+        let result = check_if_dir_matches_slots(&[limited, save], path);
+        assert_eq!(
+            result.unwrap(),
+            Some(RejectReason::TooManyFiles {
+                slot_id: 101,
+                filename: String::from("*.bin"),
+                count: 3,
+                max_count: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_case_only_differences() {
+        let folder_path = Path::new("common/Pokemon");
+        let slot_matches = vec![
+            vec![
+                folder_path.join("Pokemon.bin"),
+                folder_path.join("pokemon.bin"),
+            ],
+            vec![
+                folder_path.join("pokemon.bin"),
+                folder_path.join("Disc/POKEMON.BIN"),
+            ],
+            vec![folder_path.join("disc/pokemon.bin")],
+        ];
 
-        assert!(matches!(result, Ok(false)));
+        assert_eq!(
+            case_only_differences(folder_path, &slot_matches),
+            vec![
+                FolderWarning::CaseOnlyDifference {
+                    file: String::from("Disc/POKEMON.BIN"),
+                    other: String::from("disc/pokemon.bin")
+                },
+                FolderWarning::CaseOnlyDifference {
+                    file: String::from("Pokemon.bin"),
+                    other: String::from("pokemon.bin")
+                },
+            ]
+        );
     }
 }
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{relative_to, Event, PackagerError, PlannedInstance, Reporter};
+
+/// The instance files the packager has made for a core, so they can be told apart from hand written ones.
+///
+/// Lives in the core's output folder, paths are relative to that folder & always use `/`.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub(crate) struct Manifest {
+    pub(crate) files: BTreeSet<String>,
+}
+
+pub(crate) fn manifest_path(output_folder: &Path, core_name: &str) -> PathBuf {
+    output_folder.join(format!(".instance-packager-{}.manifest", core_name))
+}
+
+impl Manifest {
+    /// A missing or unreadable manifest is treated as empty, since that only means nothing gets cleaned up.
+    pub(crate) fn read(output_folder: &Path, core_name: &str) -> Manifest {
+        fs::read_to_string(manifest_path(output_folder, core_name))
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn write(&self, output_folder: &Path, core_name: &str) -> Result<(), PackagerError> {
+        let path = manifest_path(output_folder, core_name);
+        let write_error = |source| PackagerError::Write {
+            path: path.clone(),
+            source,
+        };
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|err| write_error(std::io::Error::from(err)))?;
+        fs::create_dir_all(output_folder).map_err(write_error)?;
+        fs::write(&path, contents).map_err(write_error)
+    }
+}
+
+/// The files recorded in the manifests of any other cores writing to the same output folder,
+/// keyed by the core's name.
+///
+/// Files which have since been deleted are left out, so a removed core's old manifest doesn't take names.
+pub(crate) fn other_cores_files(output_folder: &Path, core_name: &str) -> Vec<(String, PathBuf)> {
+    let own_manifest = manifest_path(output_folder, core_name);
+    let Ok(entries) = fs::read_dir(output_folder) else {
+        return vec![];
+    };
+
+    let mut files = vec![];
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let other_core = entry.file_name().to_str().and_then(|name| {
+            name.strip_prefix(".instance-packager-")
+                .and_then(|name| name.strip_suffix(".manifest"))
+                .map(String::from)
+        });
+
+        if let (Some(other_core), true) = (other_core, path != own_manifest) {
+            let manifest = Manifest::read(output_folder, &other_core);
+            files.extend(
+                manifest
+                    .files
+                    .into_iter()
+                    .map(|entry| (other_core.clone(), output_folder.join(entry)))
+                    .filter(|(_, file_path)| file_path.exists()),
+            );
+        }
+    }
+    files.sort();
+    files
+}
+
+fn manifest_entry(output_folder: &Path, file_path: &Path) -> Option<String> {
+    let relative = file_path.strip_prefix(output_folder).ok()?;
+    let parts: Option<Vec<&str>> = relative.iter().map(|p| p.to_str()).collect();
+    Some(parts?.join("/"))
+}
+
+/// Entries for the instance files the plan makes, i.e. everything not skipped.
+fn planned_entries(output_folder: &Path, plan: &[PlannedInstance]) -> BTreeSet<String> {
+    plan.iter()
+        .filter(|planned| planned.will_write())
+        .filter_map(|planned| manifest_entry(output_folder, &planned.output_path))
+        .collect()
+}
+
+/// Entries for output paths more than one game wants, whatever an earlier run wrote there is left alone
+/// until the collision is sorted out.
+fn colliding_entries(output_folder: &Path, plan: &[PlannedInstance]) -> BTreeSet<String> {
+    plan.iter()
+        .filter(|planned| planned.output_collision)
+        .filter_map(|planned| manifest_entry(output_folder, &planned.output_path))
+        .collect()
+}
+
+pub(crate) fn find_orphans(
+    output_folder: &Path,
+    core_name: &str,
+    plan: &[PlannedInstance],
+) -> Vec<PathBuf> {
+    let mut kept = planned_entries(output_folder, plan);
+    kept.extend(colliding_entries(output_folder, plan));
+    Manifest::read(output_folder, core_name)
+        .files
+        .difference(&kept)
+        .map(|entry| output_folder.join(entry))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Records the plan's files in the manifest, removing the orphaned ones first when `clean` is set.
+///
+/// Orphans that aren't removed stay in the manifest so a later clean can still find them.
+pub(crate) fn update(
+    root_path: &Path,
+    output_folder: &Path,
+    core_name: &str,
+    plan: &[PlannedInstance],
+    clean: bool,
+    reporter: &impl Reporter,
+) -> Result<(), PackagerError> {
+    let previous = Manifest::read(output_folder, core_name);
+    let mut manifest = Manifest {
+        files: planned_entries(output_folder, plan)
+            .into_iter()
+            .filter(|entry| output_folder.join(entry).exists())
+            .chain(
+                colliding_entries(output_folder, plan)
+                    .into_iter()
+                    .filter(|entry| previous.files.contains(entry)),
+            )
+            .collect(),
+    };
+
+    for orphan in find_orphans(output_folder, core_name, plan) {
+        let entry = manifest_entry(output_folder, &orphan);
+        let path = relative_to(&orphan, root_path).to_path_buf();
+
+        if !clean {
+            manifest.files.extend(entry);
+            continue;
+        }
+
+        match fs::remove_file(&orphan) {
+            Ok(()) => {
+                remove_empty_parents(&orphan, output_folder);
+                reporter.report(Event::InstanceRemoved { path });
+            }
+            Err(err) => {
+                manifest.files.extend(entry);
+                reporter.report(Event::IoError {
+                    path,
+                    message: err.to_string(),
+                });
+            }
+        }
+    }
+
+    if manifest != previous {
+        manifest.write(output_folder, core_name)?;
+    }
+    Ok(())
+}
+
+// only matters with `keep_file_tree`, where removing the last game in a folder would leave it empty
+fn remove_empty_parents(file_path: &Path, output_folder: &Path) {
+    for parent in file_path.ancestors().skip(1) {
+        if parent == output_folder || !parent.starts_with(output_folder) {
+            break;
+        }
+        if fs::remove_dir(parent).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::glob_stuff::NameMatching;
+use crate::template::Sources;
+use crate::{
+    ComputedData, FolderWarning, InstancePackagerDataSlot, InstancePackagerMemoryWrite, MemoryData,
+    MemoryValue, MemoryWrite, PackagerError,
+};
+
+/// Works out the data of a game's memory writes, leaving out those with nothing to work it out from.
+///
+/// `folder` is the game folder's path relative to the asset folder, used by `lookup` tables, whose
+/// keys are compared with it the way `matching` says. Writes reading a slot id none of the slots give
+/// out are left out with a warning, `id_offset` is the gap between discs' ids in the combined
+/// multi-disc mode.
+pub(crate) fn evaluate(
+    writes: &[InstancePackagerMemoryWrite],
+    folder: &str,
+    sources: &Sources,
+    matching: NameMatching,
+    id_offset: Option<usize>,
+) -> Result<(Vec<MemoryWrite>, Vec<FolderWarning>), PackagerError> {
+    let mut evaluated = vec![];
+    let mut warnings = vec![];
+    for write in writes {
+        let data = match &write.data {
+            MemoryData::Value(value) => Some(value.clone()),
+            MemoryData::Computed(computed) => {
+                if let Some(slot_id) = undeclared_slot(computed, sources.slots, id_offset) {
+                    warnings.push(FolderWarning::UndeclaredSlot { slot_id });
+                    continue;
+                }
+                compute(computed, folder, sources, matching)?
+            }
+        };
+        if let Some(data) = data {
+            evaluated.push(MemoryWrite {
+                address: write.address.clone(),
+                data,
+            });
+        }
+    }
+    Ok((evaluated, warnings))
+}
+
+/// No game has this many discs, it only stops a tiny `id_offset` making the search run on.
+pub(crate) const MAX_DISCS: usize = 256;
+
+/// The slot id a computed write reads, if no slot can give it out. `size` & `crc32` read the id a file
+/// was given, which can be on any disc of a combined game, while `count` reads a slot's own id.
+fn undeclared_slot(
+    computed: &ComputedData,
+    slots: &[InstancePackagerDataSlot],
+    id_offset: Option<usize>,
+) -> Option<usize> {
+    let (id, declared) = match computed {
+        ComputedData::Size { size: id } | ComputedData::Crc32 { crc32: id } => {
+            let on_any_disc = (0..MAX_DISCS)
+                .map_while(|disc| match id_offset.filter(|offset| *offset > 0) {
+                    Some(offset) => id.checked_sub(disc.checked_mul(offset)?),
+                    None => (disc == 0).then_some(*id),
+                })
+                .any(|id| slots.iter().any(|slot| slot.gives_id(id)));
+            (*id, on_any_disc)
+        }
+        ComputedData::Count { count: id } => (*id, slots.iter().any(|slot| slot.id == *id)),
+        ComputedData::Lookup { .. } => return None,
+    };
+    (!declared).then_some(id)
+}
+
+fn compute(
+    computed: &ComputedData,
+    folder: &str,
+    sources: &Sources,
+    matching: NameMatching,
+) -> Result<Option<MemoryValue>, PackagerError> {
+    let file_path = |id: usize| {
+        sources
+            .data_slots
+            .iter()
+            .find(|slot| slot.id == id)
+            .map(|slot| sources.folder_path.join(&slot.filename))
+    };
+    let unreadable = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| PackagerError::UnreadableFile { path, source }
+    };
+
+    Ok(match computed {
+        ComputedData::Size { size } => match file_path(*size) {
+            Some(path) => {
+                let metadata = fs::metadata(&path).map_err(unreadable(&path))?;
+                Some(MemoryValue::Number(metadata.len() as usize))
+            }
+            None => None,
+        },
+        ComputedData::Count { count } => sources
+            .slots
+            .iter()
+            .position(|slot| slot.id == *count)
+            .map(|index| MemoryValue::Number(sources.slot_matches[index].len())),
+        ComputedData::Crc32 { crc32 } => match file_path(*crc32) {
+            Some(path) => {
+                let crc = file_crc32(&path).map_err(unreadable(&path))?;
+                Some(MemoryValue::Number(crc as usize))
+            }
+            None => None,
+        },
+        ComputedData::Lookup { lookup, default } => lookup_value(lookup, folder, matching)
+            .or_else(|| {
+                lookup_value(
+                    lookup,
+                    folder.rsplit('/').next().unwrap_or(folder),
+                    matching,
+                )
+            })
+            .or(default.as_ref())
+            .cloned(),
+    })
+}
+
+/// An exact key first, then the first key (in key order) that's the same once `matching` is applied.
+fn lookup_value<'a>(
+    lookup: &'a HashMap<String, MemoryValue>,
+    target: &str,
+    matching: NameMatching,
+) -> Option<&'a MemoryValue> {
+    lookup.get(target).or_else(|| {
+        let target = matching.key(target);
+        lookup
+            .iter()
+            .filter(|(key, _)| matching.key(key) == target)
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, value)| value)
+    })
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+/// The same CRC32 zip & No-Intro dats use, read in chunks since disc images can be large.
+fn file_crc32(path: &Path) -> io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut crc = !0u32;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(!crc);
+        }
+        for byte in &buffer[..read] {
+            crc = (crc >> 8) ^ CRC32_TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DataSlot, InstancePackagerDataSlot};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_evaluate() {
+        let temp_dir = tempdir().unwrap();
+        let folder_path = temp_dir.path().join("jp/Game");
+        fs::create_dir_all(&folder_path).unwrap();
+        fs::write(folder_path.join("game.bin"), "123456789").unwrap();
+
+        let slots = vec![InstancePackagerDataSlot {
+            id: 100,
+            filename: String::from("*.bin"),
+            ..Default::default()
+        }];
+        let slot_matches = vec![vec![folder_path.join("game.bin")]];
+        let data_slots = vec![DataSlot {
+            id: 100,
+            filename: String::from("game.bin"),
+        }];
+        let sources = Sources {
+            folder_path: &folder_path,
+            slots: &slots,
+            slot_matches: &slot_matches,
+            data_slots: &data_slots,
+        };
+        let writes: Vec<InstancePackagerMemoryWrite> = serde_json::from_value(serde_json::json!([
+            { "address": 0, "data": "0x1" },
+            { "address": 4, "data": { "size": 100 } },
+            { "address": 8, "data": { "count": 100 } },
+            { "address": 12, "data": { "crc32": 100 } },
+            { "address": 16, "data": { "size": 101 } },
+            { "address": 20, "data": { "lookup": { "jp/Game": 1, "Game": 2 } } },
+            { "address": 24, "data": { "lookup": { "Game": 2 } } },
+            { "address": 28, "data": { "lookup": { "Other": 3 }, "default": 4 } },
+            { "address": 32, "data": { "lookup": { "Other": 3 } } },
+            { "address": 36, "data": { "size": 99 } },
+            { "address": 40, "data": { "count": 101 } }
+        ]))
+        .unwrap();
+
+        let data = |folder| -> Vec<(MemoryValue, MemoryValue)> {
+            evaluate(&writes, folder, &sources, NameMatching::default(), None)
+                .unwrap()
+                .0
+                .into_iter()
+                .map(|write| (write.address, write.data))
+                .collect()
+        };
+        let number = MemoryValue::Number;
+        assert_eq!(
+            data("jp/Game"),
+            vec![
+                (number(0), MemoryValue::String(String::from("0x1"))),
+                (number(4), number(9)),
+                (number(8), number(1)),
+                // the check value for CRC32
+                (number(12), number(0xCBF4_3926)),
+                (number(20), number(1)),
+                (number(24), number(2)),
+                (number(28), number(4)),
+            ]
+        );
+        assert_eq!(data("usa/Game")[4], (number(20), number(2)));
+        // no slot gives out 99, & 101 is only the id of slot 100's second file
+        let (_, warnings) =
+            evaluate(&writes, "jp/Game", &sources, NameMatching::default(), None).unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                FolderWarning::UndeclaredSlot { slot_id: 99 },
+                FolderWarning::UndeclaredSlot { slot_id: 101 }
+            ]
+        );
+        // only the lookup with a default gives a value when case counts
+        assert_eq!(data("usa/GAME").len(), 5);
+
+        let matching = NameMatching {
+            case_insensitive: true,
+            normalize_unicode: false,
+        };
+        let (lookups, _) = evaluate(&writes[5..9], "usa/GAME", &sources, matching, None).unwrap();
+        assert_eq!(
+            lookups
+                .into_iter()
+                .map(|write| write.data)
+                .collect::<Vec<_>>(),
+            vec![number(2), number(2), number(4)]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::glob_stuff::relative_name;
+use crate::report::{Event, RejectReason, Reporter};
+use crate::{
+    memory_writes, relative_to, template, DataSlot, MultiDisc, PackagerConfig, PackagerError,
+    PlannedInstance, ScannedGame,
+};
+
+/// Which disc of a multi-disc game an instance is for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disc {
+    /// The game's name without the disc number, e.g. `Game (USA)` for `Game (Disc 2) (USA)`
+    pub game: String,
+    pub number: usize,
+    /// Discs of the same game share this folder, it's the folder holding the disc folders
+    /// or the game folder itself for playlists & discs side by side in one folder
+    pub group_folder: PathBuf,
+    /// The files each data slot matched for this disc, by slot id, which a combined game's
+    /// memory writes are worked out from
+    pub(crate) slot_matches: Vec<(usize, Vec<PathBuf>)>,
+}
+
+static DISC_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\s*[(\[]\s*(?:disc|disk|cd)\s*(\d+)(?:\s*of\s*\d+)?\s*[)\]]")
+        .expect("the disc tag regex is valid")
+});
+
+/// Spots `(Disc N)`, `(Disc N of M)` or `[CD N]` in a folder or file name.
+pub(crate) fn disc_from_name(name: &str, group_folder: &Path) -> Option<Disc> {
+    let captures = DISC_TAG.captures(name)?;
+    let tag = captures.get(0)?;
+    let game = format!("{}{}", &name[..tag.start()], &name[tag.end()..]);
+    Some(Disc {
+        game: game.split_whitespace().collect::<Vec<_>>().join(" "),
+        number: captures[1].parse().ok()?,
+        group_folder: group_folder.to_path_buf(),
+        slot_matches: vec![],
+    })
+}
+
+/// An `.m3u` listing a game's discs, entries are relative to the folder it's in.
+pub(crate) struct Playlist {
+    pub(crate) path: PathBuf,
+    pub(crate) entries: Vec<String>,
+}
+
+/// The first `.m3u` directly in the folder, if there is one.
+pub(crate) fn read_playlist(folder_path: &Path) -> Result<Option<Playlist>, PackagerError> {
+    let Ok(entries) = fs::read_dir(folder_path) else {
+        return Ok(None);
+    };
+    let mut playlists: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("m3u"))
+                && path
+                    .file_name()
+                    .is_some_and(|name| !name.to_string_lossy().starts_with('.'))
+        })
+        .collect();
+    playlists.sort();
+    let Some(path) = playlists.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let contents = fs::read(&path).map_err(|source| PackagerError::UnreadableFile {
+        path: path.clone(),
+        source,
+    })?;
+    let entries = String::from_utf8_lossy(&contents)
+        .lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.replace('\\', "/"))
+        .collect();
+    Ok(Some(Playlist { path, entries }))
+}
+
+/// Moves the discs of each game with more than one into a subfolder, or merges them into one instance,
+/// depending on the `MultiDisc` mode.
+///
+/// Everything else in the plan is left as it was, the game takes the place of its first disc. A game
+/// that can't be combined is reported & left out.
+pub(crate) fn group_discs(
+    plan: Vec<PlannedInstance>,
+    multi_disc: &MultiDisc,
+    config: &PackagerConfig,
+    root_path: &Path,
+    reporter: &impl Reporter,
+) -> Vec<PlannedInstance> {
+    let asset_folder = config.asset_folder(root_path);
+    let mut groups: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (index, planned) in plan.iter().enumerate() {
+        if let Some(disc) = &planned.disc {
+            groups
+                .entry((disc.group_folder.clone(), disc.game.clone()))
+                .or_default()
+                .push(index);
+        }
+    }
+    let mut group_of: HashMap<usize, Vec<usize>> = HashMap::new();
+    for indices in groups.into_values().filter(|indices| indices.len() > 1) {
+        group_of.insert(indices[0], indices);
+    }
+
+    let mut slots: Vec<Option<PlannedInstance>> = plan.into_iter().map(Some).collect();
+    let mut grouped = vec![];
+    for index in 0..slots.len() {
+        if let Some(indices) = group_of.get(&index) {
+            let mut discs: Vec<PlannedInstance> =
+                indices.iter().filter_map(|&i| slots[i].take()).collect();
+            discs.sort_by_key(|planned| planned.disc.as_ref().map(|disc| disc.number));
+            match multi_disc {
+                MultiDisc::Subfolder => grouped.extend(discs.into_iter().map(into_subfolder)),
+                MultiDisc::Combined { id_offset } => {
+                    match combine(discs, *id_offset, config, &asset_folder) {
+                        Ok(ScannedGame::Planned(planned)) => grouped.push(*planned),
+                        Ok(ScannedGame::Rejected { path, reason }) => {
+                            reporter.report(Event::FolderRejected {
+                                folder: relative_to(&path, root_path).to_path_buf(),
+                                reason,
+                            })
+                        }
+                        Err(PackagerError::UnreadableFile { path, source }) => {
+                            reporter.report(Event::IoError {
+                                path: relative_to(&path, root_path).to_path_buf(),
+                                message: source.to_string(),
+                            })
+                        }
+                        Err(err) => reporter.report(Event::IoError {
+                            path: relative_to(&asset_folder, root_path).to_path_buf(),
+                            message: err.to_string(),
+                        }),
+                    }
+                }
+            }
+        } else {
+            // the later discs of a game have already been taken
+            grouped.extend(slots[index].take());
+        }
+    }
+    grouped
+}
+
+fn into_subfolder(mut planned: PlannedInstance) -> PlannedInstance {
+    if let (Some(disc), Some(parent), Some(file_name)) = (
+        &planned.disc,
+        planned.output_path.parent(),
+        planned.output_path.file_name(),
+    ) {
+        planned.output_path = parent.join(&disc.game).join(file_name);
+    }
+    planned
+}
+
+/// One instance named after the game, with the files relative to the folder the discs are grouped in.
+///
+/// The game is turned away if `id_offset` isn't enough to keep the discs' ids apart, or is so big the
+/// ids would overflow. Its memory writes are worked out again from every disc's files, using the first
+/// disc's config.
+fn combine(
+    discs: Vec<PlannedInstance>,
+    id_offset: usize,
+    config: &PackagerConfig,
+    asset_folder: &Path,
+) -> Result<ScannedGame, PackagerError> {
+    let first = &discs[0];
+    let disc = first.disc.clone().expect("only discs are combined");
+    let mut instance_json = first.instance_json.clone();
+
+    let group_path = relative_to(&disc.group_folder, asset_folder).to_string_lossy();
+    // discs straight in the assets folder leave the data path empty
+    instance_json.instance.data_path = match group_path.as_ref() {
+        "" => String::new(),
+        group_path => format!("{}/", group_path.replace(path::MAIN_SEPARATOR, "/")),
+    };
+    instance_json.instance.data_slots = vec![];
+
+    let rejected = |reason| {
+        let path = first.primary_file.as_ref().unwrap_or(&first.source_folder);
+        Ok(ScannedGame::Rejected {
+            path: path.clone(),
+            reason,
+        })
+    };
+    let mut taken: HashMap<usize, usize> = HashMap::new();
+    for (index, planned) in discs.iter().enumerate() {
+        let prefix = relative_to(&planned.source_folder, &disc.group_folder).to_string_lossy();
+        let prefix = prefix.replace(path::MAIN_SEPARATOR, "/");
+        for slot in &planned.instance_json.instance.data_slots {
+            let Some(id) = index
+                .checked_mul(id_offset)
+                .and_then(|offset| slot.id.checked_add(offset))
+            else {
+                return rejected(RejectReason::IdOutOfRange { slot_id: slot.id });
+            };
+            let filename = match prefix.as_str() {
+                "" => slot.filename.clone(),
+                prefix => format!("{prefix}/{}", slot.filename),
+            };
+            if let Some(&other) = taken.get(&id) {
+                return rejected(RejectReason::DiscIdCollision {
+                    id,
+                    file: filename,
+                    other: instance_json.instance.data_slots[other].filename.clone(),
+                });
+            }
+            taken.insert(id, instance_json.instance.data_slots.len());
+            instance_json
+                .instance
+                .data_slots
+                .push(DataSlot { id, filename });
+        }
+    }
+
+    let folder = relative_name(&first.source_folder, asset_folder);
+    let slots = config.get_slots(&folder);
+    let slot_matches: Vec<Vec<PathBuf>> = slots
+        .iter()
+        .map(|slot| {
+            discs
+                .iter()
+                .filter_map(|planned| planned.disc.as_ref())
+                .flat_map(|disc| &disc.slot_matches)
+                .filter(|(id, _)| *id == slot.id)
+                .flat_map(|(_, paths)| paths.iter().cloned())
+                .collect()
+        })
+        .collect();
+    let sources = template::Sources {
+        folder_path: &disc.group_folder,
+        slots: &slots,
+        slot_matches: &slot_matches,
+        data_slots: &instance_json.instance.data_slots,
+    };
+    // each disc has already warned about writes that read undeclared slots
+    (instance_json.instance.memory_writes, _) = memory_writes::evaluate(
+        &config.get_memory_writes(&folder),
+        &folder,
+        &sources,
+        config.name_matching(),
+        Some(id_offset),
+    )?;
+
+    let slot_limit_exceeded = config
+        .slot_limit
+        .as_ref()
+        .filter(|slot_limit| instance_json.instance.data_slots.len() > slot_limit.count)
+        .map(|slot_limit| slot_limit.message.clone());
+
+    Ok(ScannedGame::Planned(Box::new(PlannedInstance {
+        source_folder: disc.group_folder.clone(),
+        primary_file: None,
+        output_path: first
+            .output_path
+            .with_file_name(format!("{}.json", disc.game)),
+        instance_json,
+        applied_override: first.applied_override.clone(),
+        slot_limit_exceeded,
+        output_collision: false,
+        warnings: discs
+            .iter()
+            .flat_map(|planned| planned.warnings.clone())
+            .collect(),
+        disc: None,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disc_from_name() {
+        let folder = Path::new("games");
+        let disc = |name| disc_from_name(name, folder).map(|disc| (disc.game, disc.number));
+
+        assert_eq!(
+            disc("Game (Disc 2) (USA)"),
+            Some((String::from("Game (USA)"), 2))
+        );
+        assert_eq!(disc("Game (disc 1 of 3)"), Some((String::from("Game"), 1)));
+        assert_eq!(disc("Game [CD2]"), Some((String::from("Game"), 2)));
+        assert_eq!(disc("Discworld (USA)"), None);
+    }
+}
@@ -0,0 +1,286 @@
+use glob::Pattern;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+use crate::glob_stuff::NameMatching;
+use crate::InstancePackagerOverrides;
+
+/// How an `overrides` key picks the game folders it's used for.
+#[derive(Debug, Clone)]
+pub(crate) enum Matcher {
+    /// A plain folder name, or path like `jp/Game`, kept as `NameMatching::key` has it
+    Exact(String),
+    /// A key with `*`, `?` or `[`, e.g. `kof9*`
+    Glob(Pattern),
+    /// A key wrapped in slashes, e.g. `/^mslug[0-9x]$/`, which only has to match part of the name
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct OverrideKey {
+    pub(crate) key: String,
+    pub(crate) matcher: Matcher,
+    /// Keys with a `/` (other than regexes) are matched against the game folder's path relative to
+    /// the asset folder, rather than its name, and beat every key that isn't
+    pub(crate) path: bool,
+    /// Roughly how many characters of a folder name the key pins down, exact keys beat every pattern
+    pub(crate) specificity: usize,
+    matching: NameMatching,
+}
+
+impl OverrideKey {
+    pub(crate) fn parse(key: &str, matching: NameMatching) -> Result<OverrideKey, String> {
+        let normalized = matching.normalize(key);
+        let (matcher, specificity) = if let Some(pattern) = regex_pattern(&normalized) {
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(matching.case_insensitive)
+                .build()
+                .map_err(|err| err.to_string())?;
+            (Matcher::Regex(regex), regex_specificity(pattern))
+        } else if key.contains(['*', '?', '[']) {
+            let pattern = Pattern::new(&normalized).map_err(|err| {
+                format!(
+                    "{}, write [ as [[] & ] as []] to match them literally",
+                    err.msg
+                )
+            })?;
+            (Matcher::Glob(pattern), glob_specificity(key))
+        } else {
+            (Matcher::Exact(matching.key(key)), usize::MAX)
+        };
+        Ok(OverrideKey {
+            key: String::from(key),
+            path: !matches!(matcher, Matcher::Regex(_)) && key.contains('/'),
+            matcher,
+            specificity,
+            matching,
+        })
+    }
+
+    /// `folder` is the game folder's path relative to the asset folder, with `/` between folders.
+    pub(crate) fn matches(&self, folder: &str) -> bool {
+        let target = if self.path {
+            folder
+        } else {
+            folder.rsplit('/').next().unwrap_or(folder)
+        };
+        // the literal separators mean `jp/*` doesn't reach into `jp/Game/Disc 1`
+        match &self.matcher {
+            Matcher::Exact(key) => *key == self.matching.key(target),
+            Matcher::Glob(pattern) => {
+                self.is_literally(target)
+                    || pattern.matches_with(
+                        &self.matching.normalize(target),
+                        self.matching.glob_options(),
+                    )
+            }
+            Matcher::Regex(regex) => regex.is_match(&self.matching.normalize(target)),
+        }
+    }
+
+    /// A glob key that's exactly the folder's name, like `Game [USA]`, matches it as an exact key would.
+    fn is_literally(&self, target: &str) -> bool {
+        matches!(self.matcher, Matcher::Glob(_))
+            && self.matching.key(&self.key) == self.matching.key(target)
+    }
+
+    /// How well the key matches a folder it matches, path keys beat name keys, then the most specific.
+    pub(crate) fn rank(&self, folder: &str) -> (bool, usize) {
+        let target = if self.path {
+            folder
+        } else {
+            folder.rsplit('/').next().unwrap_or(folder)
+        };
+        if self.is_literally(target) {
+            (self.path, usize::MAX)
+        } else {
+            (self.path, self.specificity)
+        }
+    }
+
+    pub(crate) fn is_pattern(&self) -> bool {
+        !matches!(self.matcher, Matcher::Exact(_))
+    }
+}
+
+fn regex_pattern(key: &str) -> Option<&str> {
+    key.strip_prefix('/')?.strip_suffix('/')
+}
+
+/// Every key that can be parsed, in key order so ties are always broken the same way.
+pub(crate) fn parse_keys(
+    overrides: &HashMap<String, InstancePackagerOverrides>,
+    matching: NameMatching,
+) -> Vec<OverrideKey> {
+    let mut keys: Vec<OverrideKey> = overrides
+        .keys()
+        .filter_map(|key| OverrideKey::parse(key, matching).ok())
+        .collect();
+    keys.sort_by(|a, b| a.key.cmp(&b.key));
+    keys
+}
+
+/// The keys matching a folder, the one used first.
+///
+/// Path keys beat name keys, then an exact key beats any pattern & more specific patterns beat less
+/// specific ones.
+pub(crate) fn matching<'a>(keys: &'a [OverrideKey], folder: &str) -> Vec<&'a OverrideKey> {
+    let mut matched: Vec<&OverrideKey> = keys.iter().filter(|key| key.matches(folder)).collect();
+    // stable, so equally specific keys stay in key order
+    matched.sort_by_key(|key| std::cmp::Reverse(key.rank(folder)));
+    matched
+}
+
+/// Literal characters count one, `[...]` classes count one & wildcards count nothing.
+fn glob_specificity(pattern: &str) -> usize {
+    let mut count = 0;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' => {}
+            '[' => {
+                chars.by_ref().find(|c| *c == ']');
+                count += 1;
+            }
+            _ => count += 1,
+        }
+    }
+    count
+}
+
+/// Like `glob_specificity`, escapes & `[...]` classes count one, other metacharacters & `{n,m}` count nothing.
+fn regex_specificity(pattern: &str) -> usize {
+    let mut count = 0;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+                count += 1;
+            }
+            '[' => {
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' if !escaped => break,
+                        '\\' => escaped = !escaped,
+                        _ => escaped = false,
+                    }
+                }
+                count += 1;
+            }
+            '{' => {
+                chars.by_ref().find(|c| *c == '}');
+            }
+            '.' | '^' | '$' | '*' | '+' | '?' | '(' | ')' | '|' => {}
+            _ => count += 1,
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching() {
+        let keys: Vec<OverrideKey> = ["kof*", "kof9*", "/^kof9[0-9]$/", "kof98", "/mslug/"]
+            .iter()
+            .map(|key| OverrideKey::parse(key, NameMatching::default()).unwrap())
+            .collect();
+        let matching = |folder_name| -> Vec<&str> {
+            matching(&keys, folder_name)
+                .iter()
+                .map(|key| key.key.as_str())
+                .collect()
+        };
+
+        assert_eq!(
+            matching("kof98"),
+            vec!["kof98", "/^kof9[0-9]$/", "kof9*", "kof*"]
+        );
+        assert_eq!(matching("kof97"), vec!["/^kof9[0-9]$/", "kof9*", "kof*"]);
+        assert_eq!(matching("kof2000"), vec!["kof*"]);
+        assert_eq!(matching("mslugx"), vec!["/mslug/"]);
+        assert!(matching("samsho").is_empty());
+    }
+
+    #[test]
+    fn test_matching_paths() {
+        let keys: Vec<OverrideKey> = ["Game", "G*", "jp/Game", "jp/*", "/^jp/"]
+            .iter()
+            .map(|key| OverrideKey::parse(key, NameMatching::default()).unwrap())
+            .collect();
+        let matching = |folder| -> Vec<&str> {
+            matching(&keys, folder)
+                .iter()
+                .map(|key| key.key.as_str())
+                .collect()
+        };
+
+        assert_eq!(matching("jp/Game"), vec!["jp/Game", "jp/*", "Game", "G*"]);
+        assert_eq!(matching("usa/Game"), vec!["Game", "G*"]);
+        assert_eq!(matching("jp/Games/Game"), vec!["Game", "G*"]);
+        // regexes only ever see the folder name
+        assert_eq!(matching("jp"), vec!["/^jp/"]);
+    }
+
+    #[test]
+    fn test_matching_case_and_unicode() {
+        let matching = NameMatching {
+            case_insensitive: true,
+            normalize_unicode: true,
+        };
+        // "Pokémon" with a precomposed é, & the folder name with e + a combining accent
+        let keys: Vec<OverrideKey> = ["Pok\u{e9}mon", "SONIC*", "/^street fighter/", "jp/Game"]
+            .iter()
+            .map(|key| OverrideKey::parse(key, matching).unwrap())
+            .collect();
+        let matched = |folder| -> Vec<&str> {
+            super::matching(&keys, folder)
+                .iter()
+                .map(|key| key.key.as_str())
+                .collect()
+        };
+
+        assert_eq!(matched("pokemon/POKE\u{301}MON"), vec!["Pok\u{e9}mon"]);
+        assert_eq!(matched("Sonic 2"), vec!["SONIC*"]);
+        assert_eq!(matched("Street Fighter II"), vec!["/^street fighter/"]);
+        assert_eq!(matched("JP/game"), vec!["jp/Game"]);
+
+        let key = OverrideKey::parse("Pok\u{e9}mon", NameMatching::default()).unwrap();
+        assert!(!key.matches("Poke\u{301}mon"));
+        assert!(!key.matches("pok\u{e9}mon"));
+    }
+
+    #[test]
+    fn test_matching_bracketed_names() {
+        let keys: Vec<OverrideKey> = ["Game [USA]", "Game*", "Game [[]USA[]]"]
+            .iter()
+            .map(|key| OverrideKey::parse(key, NameMatching::default()).unwrap())
+            .collect();
+        let matched = |folder| -> Vec<&str> {
+            matching(&keys, folder)
+                .iter()
+                .map(|key| key.key.as_str())
+                .collect()
+        };
+
+        // a glob key spelled exactly like the folder's name matches it ahead of the escaped form
+        assert_eq!(
+            matched("Game [USA]"),
+            vec!["Game [USA]", "Game [[]USA[]]", "Game*"]
+        );
+        assert_eq!(matched("Game U"), vec!["Game [USA]", "Game*"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(OverrideKey::parse("/mslug[/", NameMatching::default()).is_err());
+        assert!(OverrideKey::parse("kof[9", NameMatching::default()).is_err());
+        assert!(!OverrideKey::parse("/", NameMatching::default())
+            .unwrap()
+            .is_pattern());
+    }
+}
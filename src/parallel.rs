@@ -0,0 +1,16 @@
+//! With the `parallel` feature work is spread across threads with rayon, otherwise it's done in turn.
+//! Either way the results come back in the same order as the items.
+
+#[cfg(feature = "parallel")]
+pub(crate) fn map_in_order<T: Sync, R: Send>(
+    items: &[T],
+    f: impl Fn(&T) -> R + Sync + Send,
+) -> Vec<R> {
+    use rayon::prelude::*;
+    items.par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn map_in_order<T, R>(items: &[T], f: impl Fn(&T) -> R) -> Vec<R> {
+    items.iter().map(f).collect()
+}
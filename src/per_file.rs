@@ -0,0 +1,213 @@
+use std::path::{Path, PathBuf};
+
+use crate::glob_stuff::{filename_regex, named_captures, relative_name, Captures};
+use crate::{InstancePackagerDataSlot, PackagerError};
+
+/// The files each of a folder's slots matched, in the same order as the slots.
+pub(crate) type SlotMatches = Vec<Vec<PathBuf>>;
+
+/// The index of the slot with `"primary": true`, if the folder's slots are in one-instance-per-file mode.
+pub(crate) fn primary_slot(slots: &[InstancePackagerDataSlot]) -> Option<usize> {
+    slots.iter().position(|slot| slot.primary == Some(true))
+}
+
+/// Splits the files each slot matched in a folder into one game per primary file, e.g. each file the
+/// primary slot (`primary_index`) matched.
+///
+/// Each game gets the files which share its primary file's stem, which includes the primary file itself.
+/// A file sharing the stem of more than one primary file (`Game (USA) (Track 1).bin` with `Game.cue`
+/// & `Game (USA).cue`) goes with the longest.
+///
+/// When a file's `filename_regex` has named captures in common with the primary slot's, those
+/// captures have to be the same instead.
+pub(crate) fn group_by_primary(
+    folder_path: &Path,
+    slots: &[InstancePackagerDataSlot],
+    slot_matches: &[Vec<PathBuf>],
+    primaries: &[PathBuf],
+    primary_index: Option<usize>,
+) -> Result<Vec<(PathBuf, SlotMatches)>, PackagerError> {
+    let regexes = slots
+        .iter()
+        .map(filename_regex)
+        .collect::<Result<Vec<_>, _>>()?;
+    let captures = |slot_index: Option<usize>, path: &Path| match slot_index
+        .and_then(|slot_index| regexes[slot_index].as_ref())
+    {
+        Some(regex) => named_captures(regex, &relative_name(path, folder_path)),
+        None => vec![],
+    };
+
+    let mut games: Vec<(PathBuf, SlotMatches, Captures)> = primaries
+        .iter()
+        .map(|primary| {
+            let primary_captures = captures(primary_index, primary);
+            (
+                primary.clone(),
+                vec![vec![]; slot_matches.len()],
+                primary_captures,
+            )
+        })
+        .collect();
+
+    for (slot_index, matched) in slot_matches.iter().enumerate() {
+        for path in matched {
+            let path_captures = captures(Some(slot_index), path);
+            let owner = games
+                .iter()
+                .enumerate()
+                .filter(|(_, (primary, _, primary_captures))| {
+                    belongs_to(path, &path_captures, primary, primary_captures)
+                })
+                .max_by_key(|(_, (primary, _, _))| stem(primary).len())
+                .map(|(game_index, _)| game_index);
+            if let Some(game_index) = owner {
+                games[game_index].1[slot_index].push(path.clone());
+            }
+        }
+    }
+    Ok(games
+        .into_iter()
+        .map(|(primary, game_matches, _)| (primary, game_matches))
+        .collect())
+}
+
+pub(crate) fn stem(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn belongs_to(
+    path: &Path,
+    path_captures: &[(String, String)],
+    primary: &Path,
+    primary_captures: &[(String, String)],
+) -> bool {
+    let shared: Vec<bool> = path_captures
+        .iter()
+        .filter_map(|(group, value)| {
+            let (_, primary_value) = primary_captures.iter().find(|(g, _)| g == group)?;
+            Some(value == primary_value)
+        })
+        .collect();
+    if shared.is_empty() {
+        shares_stem(path, primary)
+    } else {
+        shared.iter().all(|same| *same)
+    }
+}
+
+/// Whether `path` goes with the `primary` file, `Game.cue` goes with `Game.sbi`, `Game (Track 1).bin`
+/// & `Game [b].bin` but not `Game 2.bin`.
+fn shares_stem(path: &Path, primary: &Path) -> bool {
+    let (file_stem, primary_stem) = (stem(path), stem(primary));
+    match file_stem.strip_prefix(&primary_stem) {
+        Some(rest) => {
+            let rest = rest.strip_prefix(' ').unwrap_or(rest);
+            rest.is_empty() || rest.starts_with(['(', '[', '.'])
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shares_stem() {
+        let primary = Path::new("games/Game.cue");
+        assert!(shares_stem(Path::new("games/Game.bin"), primary));
+        assert!(shares_stem(Path::new("games/Game (Track 1).bin"), primary));
+        assert!(shares_stem(Path::new("games/Game [b].bin"), primary));
+        assert!(!shares_stem(Path::new("games/Game 2.bin"), primary));
+        assert!(!shares_stem(Path::new("games/Gamer.bin"), primary));
+    }
+
+    #[test]
+    fn test_group_by_primary_captures() {
+        let slot = |filename_regex: &str| InstancePackagerDataSlot {
+            filename_regex: Some(String::from(filename_regex)),
+            ..Default::default()
+        };
+        let slots = vec![
+            slot(r"^(?P<name>.+) \[!\]\.cue$"),
+            slot(r"^(?P<name>.+) \(Track \d+\)\.bin$"),
+        ];
+        let slot_matches = vec![
+            vec![PathBuf::from("Game [!].cue")],
+            vec![
+                PathBuf::from("Game (Track 1).bin"),
+                PathBuf::from("Gamer (Track 1).bin"),
+            ],
+        ];
+
+        let games = group_by_primary(
+            Path::new(""),
+            &slots,
+            &slot_matches,
+            &slot_matches[0],
+            Some(0),
+        )
+        .unwrap();
+
+        // `Game (Track 1).bin` doesn't share the stem `Game [!]` but does share the `name` capture
+        assert_eq!(
+            games,
+            vec![(
+                PathBuf::from("Game [!].cue"),
+                vec![
+                    vec![PathBuf::from("Game [!].cue")],
+                    vec![PathBuf::from("Game (Track 1).bin")]
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_group_by_primary() {
+        let paths = |names: &[&str]| -> Vec<PathBuf> { names.iter().map(PathBuf::from).collect() };
+        let slot_matches = vec![
+            paths(&["Game (USA).cue", "Game.cue", "Other.cue"]),
+            paths(&[
+                "Game (Track 1).bin",
+                "Game (USA) (Track 1).bin",
+                "Game (USA) (Track 2).bin",
+                "Stray.bin",
+            ]),
+        ];
+
+        let slots = vec![InstancePackagerDataSlot::default(); 2];
+        let games = group_by_primary(
+            Path::new(""),
+            &slots,
+            &slot_matches,
+            &slot_matches[0],
+            Some(0),
+        )
+        .unwrap();
+
+        assert_eq!(
+            games,
+            vec![
+                (
+                    PathBuf::from("Game (USA).cue"),
+                    vec![
+                        paths(&["Game (USA).cue"]),
+                        paths(&["Game (USA) (Track 1).bin", "Game (USA) (Track 2).bin"])
+                    ]
+                ),
+                (
+                    PathBuf::from("Game.cue"),
+                    vec![paths(&["Game.cue"]), paths(&["Game (Track 1).bin"])]
+                ),
+                (
+                    PathBuf::from("Other.cue"),
+                    vec![paths(&["Other.cue"]), vec![]]
+                ),
+            ]
+        );
+    }
+}
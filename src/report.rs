@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+/// Something that happened while building the instance files for a core.
+///
+/// Paths are relative to the Pocket root, the same as they'd be shown to a user.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    CoreStarted {
+        core_name: String,
+    },
+    FolderScanned {
+        folder: PathBuf,
+    },
+    /// In one-instance-per-file mode `folder` is the primary file that was turned away
+    FolderRejected {
+        folder: PathBuf,
+        reason: RejectReason,
+    },
+    /// Something looks wrong with a folder (or primary file), but it still gets an instance file
+    FolderWarning {
+        folder: PathBuf,
+        warning: FolderWarning,
+    },
+    InstanceWritten {
+        path: PathBuf,
+        kind: WriteKind,
+    },
+    /// The instance file already exists with exactly the same contents
+    InstanceUnchanged {
+        path: PathBuf,
+    },
+    /// An instance file from an earlier run which wouldn't be made any more was deleted
+    InstanceRemoved {
+        path: PathBuf,
+    },
+    SlotLimitExceeded {
+        path: PathBuf,
+        message: String,
+    },
+    /// More than one game would be written to `path`, what happens next depends on the `CollisionStrategy`
+    OutputCollision {
+        path: PathBuf,
+        folders: Vec<PathBuf>,
+    },
+    /// A single file or folder couldn't be read or written, the rest of the core carries on
+    IoError {
+        path: PathBuf,
+        message: String,
+    },
+    CoreFinished {
+        core_name: String,
+        summary: CoreSummary,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteKind {
+    /// There wasn't an instance file there before
+    New,
+    /// An existing instance file had different contents
+    Updated,
+}
+
+/// Why a folder didn't get an instance file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    /// A `required` slot didn't match any files
+    MissingRequiredSlot { slot_id: usize, filename: String },
+    /// A `required` slot with `"sort": "single"` matched more than one file
+    TooManyForSingleSlot {
+        slot_id: usize,
+        filename: String,
+        count: usize,
+    },
+    /// A slot matched more files than its `max_count`
+    TooManyFiles {
+        slot_id: usize,
+        filename: String,
+        count: usize,
+        max_count: usize,
+    },
+    /// Two slots' files would be given the same id, `slot_id`'s range reaching into `other_slot_id`'s
+    SlotIdCollision {
+        slot_id: usize,
+        other_slot_id: usize,
+        id: usize,
+    },
+    /// A `"sort": "cue"` slot, but none of the other slots matched a `.cue` file
+    MissingCueSheet { slot_id: usize },
+    /// The cue sheet has a `FILE` entry for a file that isn't in the folder
+    CueMissingFile { cue: String, file: String },
+    /// The folder's `.m3u` lists a disc that isn't there
+    PlaylistMissingFile { playlist: String, file: String },
+    /// Combining a multi-disc game would give two discs' files the same id, `id_offset` is too small.
+    /// The files are relative to the folder the discs are grouped in
+    DiscIdCollision {
+        id: usize,
+        file: String,
+        other: String,
+    },
+    /// A file would be given an id past the largest one there can be, `id_stride` or `id_offset` is too big
+    IdOutOfRange { slot_id: usize },
+}
+
+/// Something odd about a folder which didn't stop its instance file being made.
+///
+/// File names are relative to the game folder.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FolderWarning {
+    /// The cue sheet names a file with different capitalisation to the one in the folder,
+    /// fine on the Pocket's SD card but not everywhere else
+    CueCaseMismatch {
+        cue: String,
+        referenced: String,
+        found: String,
+    },
+    /// A file matched by a `"sort": "cue"` slot which the cue sheet doesn't mention, it's left out of the instance
+    UnreferencedFile { cue: String, file: String },
+    /// Two files whose names only differ by case, which can't both be copied to the Pocket's SD card
+    CaseOnlyDifference { file: String, other: String },
+    /// A `size`, `count` or `crc32` memory write reads a slot id none of the data slots give out,
+    /// so the write is left out
+    UndeclaredSlot { slot_id: usize },
+}
+
+/// Counts of what happened to a core, sent with `Event::CoreFinished`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoreSummary {
+    pub folders_scanned: usize,
+    pub folders_rejected: usize,
+    pub new: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub slot_limit_exceeded: usize,
+    pub output_collisions: usize,
+    pub io_errors: usize,
+    pub warnings: usize,
+}
+
+pub trait Reporter {
+    fn report(&self, event: Event);
+}
+
+impl<F: Fn(Event)> Reporter for F {
+    fn report(&self, event: Event) {
+        self(event)
+    }
+}
+
+/// Passes events on while counting them up for the `CoreSummary`.
+pub(crate) struct Tally<'a, R: Reporter + ?Sized> {
+    inner: &'a R,
+    summary: RefCell<CoreSummary>,
+}
+
+impl<'a, R: Reporter + ?Sized> Tally<'a, R> {
+    pub(crate) fn new(inner: &'a R) -> Self {
+        Tally {
+            inner,
+            summary: RefCell::new(CoreSummary::default()),
+        }
+    }
+
+    pub(crate) fn summary(&self) -> CoreSummary {
+        self.summary.borrow().clone()
+    }
+}
+
+impl<R: Reporter + ?Sized> Reporter for Tally<'_, R> {
+    fn report(&self, event: Event) {
+        {
+            let mut summary = self.summary.borrow_mut();
+            match &event {
+                Event::FolderScanned { .. } => summary.folders_scanned += 1,
+                Event::FolderRejected { .. } => summary.folders_rejected += 1,
+                Event::InstanceWritten {
+                    kind: WriteKind::New,
+                    ..
+                } => summary.new += 1,
+                Event::InstanceWritten {
+                    kind: WriteKind::Updated,
+                    ..
+                } => summary.updated += 1,
+                Event::InstanceUnchanged { .. } => summary.unchanged += 1,
+                Event::InstanceRemoved { .. } => summary.removed += 1,
+                Event::SlotLimitExceeded { .. } => summary.slot_limit_exceeded += 1,
+                Event::OutputCollision { .. } => summary.output_collisions += 1,
+                Event::IoError { .. } => summary.io_errors += 1,
+                Event::FolderWarning { .. } => summary.warnings += 1,
+                Event::CoreStarted { .. } | Event::CoreFinished { .. } => {}
+            }
+        }
+        self.inner.report(event);
+    }
+}
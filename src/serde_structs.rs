@@ -1,146 +1,533 @@
-use crate::glob_stuff;
+use crate::exclude::Excludes;
+use crate::glob_stuff::{self, NameMatching};
+use crate::override_keys::{self, OverrideKey};
+use crate::{folder_name, PackagerError};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// A slot in `data_slots`, describing which files in a game folder go into which slot ids.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct InstancePackagerDataSlot {
+    /// The slot id, or the first id when `sort` allows multiple files
+    pub id: usize,
+    /// A glob relative to the game folder, `named_file.bin` & `*.bin` both work
+    pub filename: String,
+    /// Only the files matched by `filename` whose path (relative to the game folder) also matches this,
+    /// the named captures can sort the files, name the instance or tie files to a primary file
+    pub filename_regex: Option<String>,
+    pub sort: Sort,
+    /// Folders without matching files are skipped when this is set
+    pub required: bool,
+    /// Use the name of the matched file as the instance file name instead of the folder name,
+    /// the `name` capture of `filename_regex` if it has one, otherwise the file's stem
+    pub as_filename: Option<bool>,
+    /// Make an instance for every file this slot matches rather than one for the whole folder,
+    /// the other slots only get the files which share the matched file's stem
+    pub primary: Option<bool>,
+    /// The pattern for `Sort::Regex`, its capture groups in turn (or the whole match) are the sort key.
+    /// `filename_regex` is used when this isn't set
+    pub sort_regex: Option<String>,
+    /// The most files the slot can take, folders with more are skipped.
+    /// Reserves the ids `id` up to `id + (max_count - 1) * id_stride` for the slot
+    pub max_count: Option<usize>,
+    /// The gap between the ids given to each file when `sort` allows multiple files, 1 by default
+    pub id_stride: Option<usize>,
+    /// Globs for files the slot shouldn't take even though `filename` matches them, like audio tracks
+    pub exclude: Option<Vec<String>>,
+}
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct InstancePackagerDataSlot {
-    pub(crate) id: usize,
-    pub(crate) filename: String,
-    pub(crate) sort: Sort,
-    pub(crate) required: bool,
-    pub(crate) as_filename: Option<bool>,
+impl InstancePackagerDataSlot {
+    /// The id given to the slot's `index`th file, `None` if it's too big to be an id.
+    pub(crate) fn file_id(&self, index: usize) -> Option<usize> {
+        index
+            .checked_mul(self.id_stride.unwrap_or(1))
+            .and_then(|offset| self.id.checked_add(offset))
+    }
+
+    /// Whether one of the slot's files could be given `id`.
+    pub(crate) fn gives_id(&self, id: usize) -> bool {
+        let Some(offset) = id.checked_sub(self.id) else {
+            return false;
+        };
+        match self.id_stride.unwrap_or(1) {
+            0 => offset == 0,
+            stride => {
+                offset % stride == 0 && self.max_count.is_none_or(|count| offset / stride < count)
+            }
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
-pub(crate) enum Sort {
+pub enum Sort {
+    /// Exactly one file
+    #[default]
     Single,
+    /// Multiple files, given ids `id`, `id + 1`, ... (or `id + id_stride`, ...) in name order
     Ascending,
+    /// Multiple files, given ids `id`, `id + 1`, ... (or `id + id_stride`, ...) in reverse name order
     Descending,
+    /// Like `Ascending`, but numbers in the names are compared as numbers so `Track 2` comes before `Track 10`
+    Natural,
+    /// Multiple files ordered by the parts of their name captured by `sort_regex` (or `filename_regex`),
+    /// compared naturally
+    Regex,
+    /// Multiple files in the order of the `FILE` entries in the `.cue` file matched by another slot,
+    /// files the cue sheet doesn't mention are left out
+    Cue,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct InstancePackagerOverrides {
-    pub(crate) data_slots: Option<Vec<InstancePackagerDataSlot>>,
-    pub(crate) filename: Option<String>,
-    // don't need to do memory_writes etc since those'll come in from a merge
-    pub(crate) memory_writes: Option<Vec<SlotsCoresAndWrites>>,
-    pub(crate) core_select: Option<SlotsCoresAndWrites>,
+/// Values for a single game folder which replace the root ones, or are merged into them as set by `merge`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct InstancePackagerOverrides {
+    pub data_slots: Option<Vec<InstancePackagerDataSlot>>,
+    /// How `data_slots` & `memory_writes` are combined with the root ones, they replace them by default
+    pub merge: Option<OverrideMerge>,
+    /// Ids of root data slots to leave out, before the override's `data_slots` are merged in
+    pub remove_slots: Option<Vec<usize>>,
+    /// Sets the output file name directly, `"Game Title"` will result in `Game Title.json`
+    pub filename: Option<String>,
+    /// Replaces the root `filename_template`
+    pub filename_template: Option<String>,
+    pub memory_writes: Option<Vec<InstancePackagerMemoryWrite>>,
+    pub core_select: Option<CoreSelect>,
+    pub variant_select: Option<VariantSelect>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct OverrideMerge {
+    #[serde(default)]
+    pub data_slots: MergeMode,
+    #[serde(default)]
+    pub memory_writes: MergeMode,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMode {
+    /// The override's list is used instead of the root one
+    #[default]
+    Replace,
+    /// The override's entries go after the root ones
+    Append,
+    /// Override entries replace the root ones with the same slot id (or memory write address),
+    /// the rest go on the end
+    Patch,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct InstancePackagerSlotLimit {
-    pub(crate) count: usize,
-    pub(crate) message: String,
+pub struct InstancePackagerSlotLimit {
+    pub count: usize,
+    /// Shown to the user for any game with more than `count` slots
+    pub message: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct InstancePackager {
-    pub(crate) output: String,
-    pub(crate) data_slots: Vec<InstancePackagerDataSlot>,
-    pub(crate) overrides: Option<HashMap<String, InstancePackagerOverrides>>,
-    pub(crate) platform_id: String,
-    pub(crate) memory_writes: Option<Vec<SlotsCoresAndWrites>>,
-    pub(crate) core_select: Option<SlotsCoresAndWrites>,
-    pub(crate) slot_limit: Option<InstancePackagerSlotLimit>,
+/// How games split across several discs are handled, discs are found from an `.m3u` in the game folder
+/// or from `(Disc N)` in the names of sibling folders (or primary files).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum MultiDisc {
+    /// An instance per disc, all written to a subfolder named after the game
+    Subfolder,
+    /// A single instance with every disc's slots, disc `n`'s slot ids are moved up by `(n - 1) * id_offset`
+    Combined { id_offset: usize },
 }
 
-impl InstancePackager {
-    pub fn get_slots(&self, folder_name: &str) -> Vec<InstancePackagerDataSlot> {
-        if let Some(overides_map) = &self.overrides {
-            if let Some(data_slots) = overides_map
-                .get(folder_name)
-                .and_then(|o| o.data_slots.to_owned())
-            {
-                return data_slots.clone();
-            }
-        }
-        self.data_slots.clone()
+/// The contents of a core's `instance-packager.json`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PackagerConfig {
+    /// Folder the instance files are written to, relative to the Pocket root
+    pub output: String,
+    pub data_slots: Vec<InstancePackagerDataSlot>,
+    /// Only reachable through `overrides()` & `set_overrides()`, which keep `override_keys` in step
+    overrides: Option<HashMap<String, InstancePackagerOverrides>>,
+    /// Games are looked for in `Assets/<platform_id>/common`
+    pub platform_id: String,
+    pub memory_writes: Option<Vec<InstancePackagerMemoryWrite>>,
+    pub core_select: Option<CoreSelect>,
+    pub variant_select: Option<VariantSelect>,
+    pub slot_limit: Option<InstancePackagerSlotLimit>,
+    pub multi_disc: Option<MultiDisc>,
+    /// Builds the output file name from placeholders like `{folder}`, `{parent}`, `{stem:<slot id>}`,
+    /// `{ext:<slot id>}` & `filename_regex` captures, each optionally followed by `|trim`, `|title`,
+    /// `|upper` or `|lower`
+    pub filename_template: Option<String>,
+    /// Globs for folders & files to leave out, relative to the asset folder, like `*.bak` or `_unused/`.
+    /// Folders containing a `.no-instance` file are always left out
+    pub exclude: Option<Vec<String>>,
+    /// Match `filename` globs & `overrides` keys regardless of case, e.g. `*.bin` matches `GAME.BIN`
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// Compare names in Unicode's NFC form, so names written by macOS (NFD) match keys typed elsewhere
+    #[serde(default)]
+    pub normalize_unicode: bool,
+    /// The `overrides` keys, parsed the first time a folder is looked up, with the `NameMatching` they
+    /// were parsed for
+    #[serde(skip)]
+    override_keys: OnceLock<(NameMatching, Vec<OverrideKey>)>,
+}
+
+impl PackagerConfig {
+    /// Reads & parses an `instance-packager.json`.
+    pub fn load(path: &Path) -> Result<PackagerConfig, PackagerError> {
+        let data = fs::read_to_string(path).map_err(|source| PackagerError::UnreadableConfig {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let config: PackagerConfig = serde_json::from_str(&data)
+            .map_err(|err| PackagerError::config_parse(path.into(), err))?;
+
+        config.check_override_keys()?;
+        config.excludes()?;
+        Ok(config)
     }
 
-    pub fn get_memory_writes(&self, folder_name: &str) -> Vec<SlotsCoresAndWrites> {
-        if let Some(overides_map) = &self.overrides {
-            if let Some(memory_writes) = overides_map
-                .get(folder_name)
-                .and_then(|m| m.memory_writes.to_owned())
-            {
-                return memory_writes.clone();
-            }
+    /// Values for single game folders which replace (or merge with) the root ones, keyed by game folder
+    /// name, path relative to the asset folder (`jp/Game`), a glob like `kof9*` or a regex like
+    /// `/^mslug[0-9x]$/`.
+    pub fn overrides(&self) -> Option<&HashMap<String, InstancePackagerOverrides>> {
+        self.overrides.as_ref()
+    }
+
+    /// Replaces the overrides, failing (& leaving them as they were) if a key is an invalid glob or regex.
+    pub fn set_overrides(
+        &mut self,
+        overrides: Option<HashMap<String, InstancePackagerOverrides>>,
+    ) -> Result<(), PackagerError> {
+        let previous = std::mem::replace(&mut self.overrides, overrides);
+        if let Err(err) = self.check_override_keys() {
+            self.overrides = previous;
+            return Err(err);
         }
+        self.override_keys = OnceLock::new();
+        Ok(())
+    }
 
-        if let Some(memory_writes) = &self.memory_writes {
-            return memory_writes.clone();
-        } else {
-            return vec![];
+    fn check_override_keys(&self) -> Result<(), PackagerError> {
+        for key in self.overrides.iter().flat_map(HashMap::keys) {
+            OverrideKey::parse(key, self.name_matching()).map_err(|message| {
+                PackagerError::OverrideKey {
+                    key: key.clone(),
+                    message,
+                }
+            })?;
         }
+        Ok(())
     }
 
-    pub fn get_core_select(&self, folder_name: &str) -> Option<SlotsCoresAndWrites> {
-        if let Some(overides_map) = &self.overrides {
-            if let Some(core_select) = overides_map
-                .get(folder_name)
-                .and_then(|m| m.core_select.to_owned())
-            {
-                return Some(core_select);
-            }
+    pub(crate) fn name_matching(&self) -> NameMatching {
+        NameMatching {
+            case_insensitive: self.case_insensitive,
+            normalize_unicode: self.normalize_unicode,
         }
-        return self.core_select.to_owned();
     }
 
-    pub fn get_filename(&self, folder_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
-        let folder_name = folder_path.file_name().unwrap().to_str().unwrap();
+    pub(crate) fn excludes(&self) -> Result<Excludes, PackagerError> {
+        Excludes::new(
+            self.exclude.as_deref().unwrap_or_default(),
+            self.name_matching(),
+        )
+    }
+
+    /// Where the core's games are, `Assets/<platform_id>/common`.
+    pub fn asset_folder(&self, root_path: &Path) -> PathBuf {
+        root_path
+            .join("Assets")
+            .join(&self.platform_id)
+            .join("common")
+    }
 
-        if let Some(overides_map) = &self.overrides {
-            if let Some(filename) = overides_map
-                .get(folder_name)
-                .and_then(|m| m.filename.to_owned())
-            {
-                return Ok(String::from(filename));
+    /// The override for a game folder, along with the key it was found under.
+    ///
+    /// `folder` is the game folder's path relative to the asset folder, like `jp/Game`. Keys which are paths
+    /// win over ones which are only folder names, then a key that's an exact match beats the most specific
+    /// glob or `/regex/` key matching it.
+    pub fn get_override(&self, folder: &str) -> Option<(&str, &InstancePackagerOverrides)> {
+        let overrides = self.overrides.as_ref()?;
+        let matching = self.name_matching();
+        let parsed;
+        let key = match overrides.get_key_value(folder) {
+            Some((key, _)) => key,
+            None => {
+                let keys = match self
+                    .override_keys
+                    .get_or_init(|| (matching, override_keys::parse_keys(overrides, matching)))
+                {
+                    (cached, keys) if *cached == matching => keys,
+                    // `case_insensitive` or `normalize_unicode` changed after the keys were cached
+                    _ => {
+                        parsed = override_keys::parse_keys(overrides, matching);
+                        &parsed
+                    }
+                };
+                &override_keys::matching(keys, folder).first()?.key
             }
-        }
+        };
+        overrides
+            .get_key_value(key)
+            .map(|(key, value)| (key.as_str(), value))
+    }
 
-        let as_filename_slots: Vec<InstancePackagerDataSlot> = self
+    /// The data slots for a game folder, merged with its override if it has one.
+    pub fn get_slots(&self, folder: &str) -> Vec<InstancePackagerDataSlot> {
+        let Some((_, o)) = self.get_override(folder) else {
+            return self.data_slots.clone();
+        };
+        let removed = o.remove_slots.as_deref().unwrap_or_default();
+        let base = self
             .data_slots
-            .clone()
-            .into_iter()
-            .filter(|s| s.as_filename == Some(true))
+            .iter()
+            .filter(|slot| !removed.contains(&slot.id))
+            .cloned()
             .collect();
+        let mode = o.merge.as_ref().map(|m| m.data_slots).unwrap_or_default();
+        merge(base, o.data_slots.as_deref(), mode, |slot| slot.id)
+    }
+
+    /// The memory writes for a game folder, merged with its override if it has one.
+    pub fn get_memory_writes(&self, folder: &str) -> Vec<InstancePackagerMemoryWrite> {
+        let base = self.memory_writes.clone().unwrap_or_default();
+        let Some((_, o)) = self.get_override(folder) else {
+            return base;
+        };
+        let mode = o
+            .merge
+            .as_ref()
+            .map(|m| m.memory_writes)
+            .unwrap_or_default();
+        // `"0x00000004"`, `"0x4"` & `4` are all the same address
+        merge(base, o.memory_writes.as_deref(), mode, |write| {
+            write
+                .address
+                .as_number()
+                .ok_or_else(|| write.address.clone())
+        })
+    }
+
+    /// The core select for a game folder, from its override if it has one.
+    pub fn get_core_select(&self, folder: &str) -> Option<CoreSelect> {
+        self.get_override(folder)
+            .and_then(|(_, o)| o.core_select.clone())
+            .or_else(|| self.core_select.clone())
+    }
+
+    /// The variant select for a game folder, from its override if it has one.
+    pub fn get_variant_select(&self, folder: &str) -> Option<VariantSelect> {
+        self.get_override(folder)
+            .and_then(|(_, o)| o.variant_select.clone())
+            .or_else(|| self.variant_select.clone())
+    }
 
-        for slot in as_filename_slots {
-            let full_glob = String::from(folder_path.join(slot.filename).to_str().unwrap());
-            let paths = glob_stuff::get_glob_paths(&full_glob)?;
+    /// The filename template for a game folder, from its override if it has one.
+    pub fn get_filename_template(&self, folder: &str) -> Option<&str> {
+        self.get_override(folder)
+            .and_then(|(_, o)| o.filename_template.as_deref())
+            .or(self.filename_template.as_deref())
+    }
+
+    /// The output file name (without `.json`) for a game folder, without its `filename_template`.
+    ///
+    /// `folder` is the game folder's path relative to the asset folder, as for `get_override`.
+    ///
+    /// An override `filename` wins, then the name of the first file matched by an `as_filename` slot,
+    /// falling back to the folder name.
+    pub fn get_filename(&self, folder_path: &Path, folder: &str) -> Result<String, PackagerError> {
+        let slots = self.get_slots(folder);
+        let slot_matches = self.get_slot_matches(folder_path, folder, &slots)?;
+        self.filename_from_matches(folder_path, folder, &slots, &slot_matches)
+    }
+
+    /// The files each of `slots` matches in a game folder, without the ones the slot's or the config's
+    /// `exclude` globs leave out.
+    ///
+    /// `folder` is the game folder's path relative to the asset folder, as for `get_override`.
+    pub fn get_slot_matches(
+        &self,
+        folder_path: &Path,
+        folder: &str,
+        slots: &[InstancePackagerDataSlot],
+    ) -> Result<Vec<Vec<PathBuf>>, PackagerError> {
+        crate::match_slots(
+            folder_path,
+            folder,
+            slots,
+            self.name_matching(),
+            &self.excludes()?,
+        )
+    }
 
-            for path in paths {
-                return Ok(String::from(path.file_stem().unwrap().to_str().unwrap()));
+    /// `get_filename` for files the scan has already matched to the folder's slots.
+    pub(crate) fn filename_from_matches(
+        &self,
+        folder_path: &Path,
+        folder: &str,
+        slots: &[InstancePackagerDataSlot],
+        slot_matches: &[Vec<PathBuf>],
+    ) -> Result<String, PackagerError> {
+        let folder_name = folder_name(folder_path)?;
+
+        if let Some(filename) = self
+            .get_override(folder)
+            .and_then(|(_, o)| o.filename.clone())
+        {
+            return Ok(filename);
+        }
+
+        for (slot, paths) in slots
+            .iter()
+            .zip(slot_matches)
+            .filter(|(slot, _)| slot.as_filename == Some(true))
+        {
+            if let Some(path) = paths.first() {
+                return glob_stuff::instance_name(path, folder_path, slot);
             }
         }
 
-        return Ok(String::from(folder_name));
+        Ok(String::from(folder_name))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct InstanceJSON {
+fn merge<T: Clone, K: PartialEq>(
+    base: Vec<T>,
+    with: Option<&[T]>,
+    mode: MergeMode,
+    key: impl Fn(&T) -> K,
+) -> Vec<T> {
+    let Some(with) = with else {
+        return base;
+    };
+    match mode {
+        MergeMode::Replace => with.to_vec(),
+        MergeMode::Append => base.into_iter().chain(with.iter().cloned()).collect(),
+        MergeMode::Patch => {
+            let mut merged = base;
+            for item in with {
+                match merged
+                    .iter_mut()
+                    .find(|existing| key(existing) == key(item))
+                {
+                    Some(existing) => *existing = item.clone(),
+                    None => merged.push(item.clone()),
+                }
+            }
+            merged
+        }
+    }
+}
+
+/// An instance file, as read by the Pocket.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InstanceJSON {
     pub instance: InstanceJSONInstance,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct InstanceJSONInstance {
-    magic: String,
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InstanceJSONInstance {
+    pub magic: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub core_select: Option<SlotsCoresAndWrites>,
+    pub core_select: Option<CoreSelect>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant_select: Option<VariantSelect>,
     pub data_path: String,
-    pub data_slots: Vec<SlotsCoresAndWrites>,
-    pub memory_writes: Vec<SlotsCoresAndWrites>,
+    pub data_slots: Vec<DataSlot>,
+    pub memory_writes: Vec<MemoryWrite>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CoreSelect {
+    pub id: usize,
+    pub select: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct VariantSelect {
+    pub id: usize,
+    pub select: bool,
+}
+
+/// A data slot in an instance file, the filename is relative to `data_path`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DataSlot {
+    pub id: usize,
+    pub filename: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: MemoryValue,
+    pub data: MemoryValue,
+}
+
+/// A memory write in the config, its data can be worked out from each game's files.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct InstancePackagerMemoryWrite {
+    pub address: MemoryValue,
+    pub data: MemoryData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MemoryData {
+    /// Passed through as is
+    Value(MemoryValue),
+    Computed(ComputedData),
+}
+
+/// Memory write data worked out for each game, writes whose data can't be worked out are left out.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(untagged)]
-pub(crate) enum SlotsCoresAndWrites {
-    CoreSelect { id: usize, select: bool },
-    DataSlot { id: usize, filename: String },
-    MemoryWriteNum { address: usize, data: usize },
-    MemoryWriteStr { address: String, data: String },
+pub enum ComputedData {
+    /// The size in bytes of the file given this slot id
+    Size { size: usize },
+    /// How many files the data slot with this id matched
+    Count { count: usize },
+    /// The CRC32 of the file given this slot id
+    Crc32 { crc32: usize },
+    /// Picked by the game folder's name (or path relative to the asset folder), `default` otherwise
+    Lookup {
+        lookup: HashMap<String, MemoryValue>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        default: Option<MemoryValue>,
+    },
+}
+
+/// Memory write addresses & data can be given as numbers or as (usually hex) strings.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MemoryValue {
+    Number(usize),
+    String(String),
+}
+
+impl MemoryValue {
+    /// The value as a number, strings can be hex with a `0x` prefix or decimal.
+    pub(crate) fn as_number(&self) -> Option<usize> {
+        match self {
+            MemoryValue::Number(number) => Some(*number),
+            MemoryValue::String(string) => {
+                let string = string.trim();
+                match string
+                    .strip_prefix("0x")
+                    .or_else(|| string.strip_prefix("0X"))
+                {
+                    Some(hex) => usize::from_str_radix(hex, 16).ok(),
+                    None => string.parse().ok(),
+                }
+            }
+        }
+    }
+}
+
+impl Default for InstanceJSON {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InstanceJSON {
@@ -152,6 +539,7 @@ impl InstanceJSON {
                 data_slots: vec![],
                 memory_writes: vec![],
                 core_select: None,
+                variant_select: None,
             },
         }
     }
@@ -164,9 +552,12 @@ mod tests {
         path::PathBuf,
     };
 
-    use crate::serde_structs::SlotsCoresAndWrites;
+    use crate::serde_structs::{
+        ComputedData, CoreSelect, InstancePackagerMemoryWrite, MemoryData, MemoryValue,
+        VariantSelect,
+    };
 
-    use super::InstancePackager;
+    use super::{InstanceJSON, PackagerConfig};
     use serde_json::json;
     use tempfile::tempdir;
 
@@ -197,7 +588,7 @@ mod tests {
             }
         });
 
-        let instance_packager: InstancePackager = serde_json::from_value(json_data).unwrap();
+        let instance_packager: PackagerConfig = serde_json::from_value(json_data).unwrap();
 
         let data_slots = instance_packager.get_slots("overrider");
         assert_eq!(data_slots.len(), 1);
@@ -208,6 +599,81 @@ mod tests {
         assert_eq!(data_slots[0].filename, "file_A.bin");
     }
 
+    #[test]
+    fn test_instance_packager_get_slots_merged() {
+        let slot = |id: usize, filename: &str| json!({ "id": id, "filename": filename, "sort": "single", "required": true });
+        let json_data = json!({
+            "output": "some/folder/somewhere/",
+            "platform_id": "abc",
+            "data_slots": [slot(3, "srom"), slot(4, "prom"), slot(6, "vroma0")],
+            "memory_writes": [
+                { "address": "0x00000004", "data": "0x12345678" },
+                { "address": 8, "data": "0x1" }
+            ],
+            "overrides": {
+                "appender": {
+                    "merge": { "data_slots": "append", "memory_writes": "append" },
+                    "data_slots": [slot(5, "crom0")],
+                    "memory_writes": [{ "address": "0xc", "data": "0x2" }]
+                },
+                "patcher": {
+                    "merge": { "data_slots": "patch", "memory_writes": "patch" },
+                    "remove_slots": [6],
+                    "data_slots": [slot(4, "prom1"), slot(7, "m1")],
+                    "memory_writes": [
+                        { "address": "0x4", "data": "0x87654321" },
+                        { "address": "0X00000008", "data": "0x3" }
+                    ]
+                },
+                "remover": { "remove_slots": [3, 4] }
+            }
+        });
+
+        let instance_packager: PackagerConfig = serde_json::from_value(json_data).unwrap();
+        let slots = |folder_name| -> Vec<(usize, String)> {
+            instance_packager
+                .get_slots(folder_name)
+                .into_iter()
+                .map(|slot| (slot.id, slot.filename))
+                .collect()
+        };
+        let data = |folder_name| -> Vec<MemoryData> {
+            instance_packager
+                .get_memory_writes(folder_name)
+                .into_iter()
+                .map(|write| write.data)
+                .collect()
+        };
+        let string = |s: &str| MemoryData::Value(MemoryValue::String(String::from(s)));
+
+        assert_eq!(
+            slots("appender"),
+            vec![
+                (3, String::from("srom")),
+                (4, String::from("prom")),
+                (6, String::from("vroma0")),
+                (5, String::from("crom0"))
+            ]
+        );
+        assert_eq!(
+            data("appender"),
+            vec![string("0x12345678"), string("0x1"), string("0x2")]
+        );
+
+        assert_eq!(
+            slots("patcher"),
+            vec![
+                (3, String::from("srom")),
+                (4, String::from("prom1")),
+                (7, String::from("m1"))
+            ]
+        );
+        assert_eq!(data("patcher"), vec![string("0x87654321"), string("0x3")]);
+
+        assert_eq!(slots("remover"), vec![(6, String::from("vroma0"))]);
+        assert_eq!(data("remover"), vec![string("0x12345678"), string("0x1")]);
+    }
+
     #[test]
     fn test_instance_packager_get_memory_writes() {
         let json_data = json!({
@@ -243,20 +709,20 @@ mod tests {
             }
         });
 
-        let instance_packager: InstancePackager = serde_json::from_value(json_data).unwrap();
+        let instance_packager: PackagerConfig = serde_json::from_value(json_data).unwrap();
 
-        let expected = SlotsCoresAndWrites::MemoryWriteStr {
-            data: "0x123".to_string(),
-            address: "0x1345".to_string(),
+        let expected = InstancePackagerMemoryWrite {
+            data: MemoryData::Value(MemoryValue::String("0x123".to_string())),
+            address: MemoryValue::String("0x1345".to_string()),
         };
 
         let memory_writes = instance_packager.get_memory_writes("non_overrider");
         assert_eq!(memory_writes.len(), 1);
         assert_eq!(memory_writes[0], expected);
 
-        let expected = SlotsCoresAndWrites::MemoryWriteStr {
-            data: "0x9876".to_string(),
-            address: "0x987654".to_string(),
+        let expected = InstancePackagerMemoryWrite {
+            data: MemoryData::Value(MemoryValue::String("0x9876".to_string())),
+            address: MemoryValue::String("0x987654".to_string()),
         };
 
         let memory_writes = instance_packager.get_memory_writes("overrider");
@@ -264,6 +730,37 @@ mod tests {
         assert_eq!(memory_writes[0], expected);
     }
 
+    #[test]
+    fn test_computed_memory_data() {
+        let json_data = json!([
+            { "address": 0, "data": { "size": 100 } },
+            { "address": 4, "data": { "count": 101 } },
+            { "address": 8, "data": { "crc32": 100 } },
+            { "address": 12, "data": { "lookup": { "Game": "0x1" }, "default": 0 } }
+        ]);
+
+        let writes: Vec<InstancePackagerMemoryWrite> =
+            serde_json::from_value(json_data.clone()).unwrap();
+        let data: Vec<MemoryData> = writes.iter().map(|write| write.data.clone()).collect();
+        assert_eq!(
+            data,
+            vec![
+                MemoryData::Computed(ComputedData::Size { size: 100 }),
+                MemoryData::Computed(ComputedData::Count { count: 101 }),
+                MemoryData::Computed(ComputedData::Crc32 { crc32: 100 }),
+                MemoryData::Computed(ComputedData::Lookup {
+                    lookup: [(
+                        String::from("Game"),
+                        MemoryValue::String(String::from("0x1"))
+                    )]
+                    .into(),
+                    default: Some(MemoryValue::Number(0))
+                }),
+            ]
+        );
+        assert_eq!(serde_json::to_value(&writes).unwrap(), json_data);
+    }
+
     #[test]
     fn test_instance_packager_get_core_select() {
         let json_data = json!({
@@ -285,9 +782,9 @@ mod tests {
             }
         });
 
-        let instance_packager: InstancePackager = serde_json::from_value(json_data).unwrap();
+        let instance_packager: PackagerConfig = serde_json::from_value(json_data).unwrap();
 
-        let expected = SlotsCoresAndWrites::CoreSelect {
+        let expected = CoreSelect {
             id: 123,
             select: true,
         };
@@ -295,7 +792,7 @@ mod tests {
         let core_select = instance_packager.get_core_select("non_overrider");
         assert_eq!(core_select, Some(expected));
 
-        let expected = SlotsCoresAndWrites::CoreSelect {
+        let expected = CoreSelect {
             id: 456,
             select: false,
         };
@@ -304,6 +801,79 @@ mod tests {
         assert_eq!(core_select, Some(expected));
     }
 
+    #[test]
+    fn test_instance_packager_changed_overrides() {
+        let json_data = json!({
+            "output": "some/folder/somewhere/",
+            "platform_id": "abc",
+            "data_slots": [],
+            "overrides": { "kof9*": { "filename": "King of Fighters" } }
+        });
+        let mut instance_packager: PackagerConfig = serde_json::from_value(json_data).unwrap();
+        let key = |instance_packager: &PackagerConfig, folder| {
+            instance_packager
+                .get_override(folder)
+                .map(|(key, _)| String::from(key))
+        };
+        assert_eq!(
+            key(&instance_packager, "kof98"),
+            Some(String::from("kof9*"))
+        );
+
+        // the keys cached by the first lookup don't outlive the overrides
+        let mut overrides = instance_packager.overrides().unwrap().clone();
+        let king_of_fighters = overrides.remove("kof9*").unwrap();
+        overrides.insert(String::from("/^KOF\\d+$/"), king_of_fighters);
+        instance_packager
+            .set_overrides(Some(overrides.clone()))
+            .unwrap();
+        assert_eq!(key(&instance_packager, "kof98"), None);
+        assert_eq!(
+            key(&instance_packager, "KOF98"),
+            Some(String::from("/^KOF\\d+$/"))
+        );
+
+        instance_packager.case_insensitive = true;
+        assert_eq!(
+            key(&instance_packager, "kof98"),
+            Some(String::from("/^KOF\\d+$/"))
+        );
+
+        overrides.insert(String::from("kof[9"), Default::default());
+        assert!(instance_packager.set_overrides(Some(overrides)).is_err());
+        assert_eq!(instance_packager.overrides().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_instance_packager_get_variant_select() {
+        let json_data = json!({
+            "output": "some/folder/somewhere/",
+            "platform_id": "abc",
+            "data_slots": [
+            ],
+            "overrides": {
+                "overrider": {
+                    "variant_select": {
+                        "id": 2,
+                        "select": true
+                    }
+                }
+            }
+        });
+
+        let instance_packager: PackagerConfig = serde_json::from_value(json_data).unwrap();
+
+        assert_eq!(instance_packager.get_variant_select("non_overrider"), None);
+
+        let expected = VariantSelect {
+            id: 2,
+            select: true,
+        };
+
+        let variant_select = instance_packager.get_variant_select("overrider");
+        assert_eq!(variant_select, Some(expected));
+    }
+
     #[test]
     fn test_instance_packager_get_file_name() {
         let json_data = json!({
@@ -322,15 +892,18 @@ mod tests {
             }
         });
 
-        let instance_packager: InstancePackager = serde_json::from_value(json_data).unwrap();
+        let instance_packager: PackagerConfig = serde_json::from_value(json_data).unwrap();
 
         let file_name = instance_packager
-            .get_filename(&PathBuf::from("fake/folder/game_name"))
+            .get_filename(&PathBuf::from("fake/folder/game_name"), "game_name")
             .unwrap();
         assert_eq!(file_name, String::from("game_name"));
 
         let file_name = instance_packager
-            .get_filename(&PathBuf::from("fake/folder/overridden_file_name"))
+            .get_filename(
+                &PathBuf::from("fake/folder/overridden_file_name"),
+                "overridden_file_name",
+            )
             .unwrap();
         assert_eq!(file_name, String::from("overridden_file_name"));
     }
@@ -353,6 +926,7 @@ mod tests {
                     "required": true
                 }
             ],
+            "exclude": ["game_name/a_*.cue"],
             "overrides": {
                 "overrider": {
                     "filename": "overridden_file_name"
@@ -366,26 +940,77 @@ mod tests {
         let path = temp_dir.path();
 
         let files = vec![
+            "fake/folder/game_name/a_excluded.cue",
             "fake/folder/game_name/cue_file_name.cue",
             "fake/folder/overrider/cue_file_name.cue",
         ];
 
         for file in files {
             let full_path = path.join(file);
-            create_dir_all(&full_path.parent().unwrap()).unwrap();
+            create_dir_all(full_path.parent().unwrap()).unwrap();
             File::create(full_path).unwrap();
         }
 
-        let instance_packager: InstancePackager = serde_json::from_value(json_data).unwrap();
+        let instance_packager: PackagerConfig = serde_json::from_value(json_data).unwrap();
 
         let file_name = instance_packager
-            .get_filename(&path.join("fake/folder/game_name"))
+            .get_filename(&path.join("fake/folder/game_name"), "game_name")
             .unwrap();
         assert_eq!(file_name, String::from("cue_file_name"));
 
         let file_name = instance_packager
-            .get_filename(&path.join("fake/folder/overrider"))
+            .get_filename(&path.join("fake/folder/overrider"), "overrider")
             .unwrap();
         assert_eq!(file_name, String::from("overridden_file_name"));
     }
+
+    #[test]
+    fn test_packager_config_load() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("instance-packager.json");
+        std::fs::write(
+            &path,
+            r#"{"output": "out", "platform_id": "abc", "data_slots": [], "memory_writes": [{"address": 16, "data": "0x1"}]}"#,
+        )
+        .unwrap();
+
+        let config = PackagerConfig::load(&path).unwrap();
+        assert_eq!(config.platform_id, "abc");
+        assert_eq!(
+            config.get_memory_writes("anything"),
+            vec![InstancePackagerMemoryWrite {
+                address: MemoryValue::Number(16),
+                data: MemoryData::Value(MemoryValue::String(String::from("0x1")))
+            }]
+        );
+
+        std::fs::write(&path, "{\"output\": \"out\",").unwrap();
+        let result = PackagerConfig::load(&path);
+        assert!(matches!(
+            result,
+            Err(crate::PackagerError::ConfigParse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_instance_json_round_trip() {
+        let json_data = json!({
+            "instance": {
+                "magic": "APF_VER_1",
+                "core_select": {"id": 1, "select": true},
+                "variant_select": {"id": 2, "select": false},
+                "data_path": "game/",
+                "data_slots": [{"id": 100, "filename": "game.cue"}],
+                "memory_writes": [{"address": "0x10", "data": 7}]
+            }
+        });
+
+        let instance_json: InstanceJSON = serde_json::from_value(json_data.clone()).unwrap();
+        assert_eq!(instance_json.instance.data_slots[0].filename, "game.cue");
+        assert_eq!(
+            instance_json.instance.memory_writes[0].data,
+            MemoryValue::Number(7)
+        );
+        assert_eq!(serde_json::to_value(&instance_json).unwrap(), json_data);
+    }
 }
@@ -0,0 +1,218 @@
+use regex::Regex;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use crate::glob_stuff::{filename_regex, relative_name};
+use crate::{InstancePackagerDataSlot, PackagerError, Sort};
+
+/// Puts the files matched by a slot's glob into the order they'll be given ids in.
+///
+/// `paths` come from the glob so are already in plain name order.
+pub(crate) fn sort_paths(
+    mut paths: Vec<PathBuf>,
+    folder_path: &Path,
+    slot: &InstancePackagerDataSlot,
+) -> Result<Vec<PathBuf>, PackagerError> {
+    let name = |path: &PathBuf| relative_name(path, folder_path);
+
+    match slot.sort {
+        // `Sort::Cue` needs the other slots, so is ordered by `cue::order_by_cue` instead
+        Sort::Single | Sort::Ascending | Sort::Cue => {}
+        Sort::Descending => paths.reverse(),
+        Sort::Natural => paths.sort_by(|a, b| natural_cmp(&name(a), &name(b))),
+        Sort::Regex => {
+            let regex = sort_regex(slot)?;
+            // files the regex doesn't match go after the ones it does
+            paths.sort_by_cached_key(|path| {
+                let name = name(path);
+                let key = regex.captures(&name).map(|captures| {
+                    let groups: Vec<NaturalKey> = captures
+                        .iter()
+                        .skip(1)
+                        .map(|group| NaturalKey(group.map_or("", |m| m.as_str()).to_owned()))
+                        .collect();
+                    if groups.is_empty() {
+                        vec![NaturalKey(captures[0].to_owned())]
+                    } else {
+                        groups
+                    }
+                });
+                (key.is_none(), key.unwrap_or_default(), NaturalKey(name))
+            });
+        }
+    }
+    Ok(paths)
+}
+
+/// The slot's `sort_regex`, or its `filename_regex` when it doesn't have one.
+pub(crate) fn sort_regex(slot: &InstancePackagerDataSlot) -> Result<Regex, PackagerError> {
+    let Some(pattern) = slot.sort_regex.as_deref() else {
+        return filename_regex(slot)?.ok_or(PackagerError::MissingSortRegex { slot_id: slot.id });
+    };
+    Regex::new(pattern).map_err(|source| PackagerError::SortRegex {
+        pattern: String::from(pattern),
+        source,
+    })
+}
+
+#[derive(PartialEq, Eq)]
+struct NaturalKey(String);
+
+impl PartialOrd for NaturalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NaturalKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        natural_cmp(&self.0, &other.0)
+    }
+}
+
+/// Compares runs of digits by their value & everything else ignoring case, so `Track 2.bin` < `Track 10.bin`.
+///
+/// Names which only differ by case or leading zeros fall back to a plain comparison, so the order is total.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_chunks, mut b_chunks) = (chunks(a), chunks(b));
+    loop {
+        let ordering = match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => compare_chunks(a, b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn compare_chunks(a: &str, b: &str) -> Ordering {
+    let is_number = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+    match (is_number(a), is_number(b)) {
+        (true, true) => {
+            let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+            a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+        }
+        _ => a.to_lowercase().cmp(&b.to_lowercase()),
+    }
+}
+
+/// Splits a name into alternating runs of digits & non digits.
+fn chunks(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (chunk, remaining) = rest.split_at(end);
+        rest = remaining;
+        Some(chunk)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(sort: Sort, sort_regex: Option<&str>) -> InstancePackagerDataSlot {
+        InstancePackagerDataSlot {
+            id: 1,
+            filename: String::from("*.bin"),
+            sort,
+            sort_regex: sort_regex.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    fn sorted(names: &[&str], slot: &InstancePackagerDataSlot) -> Vec<String> {
+        let folder = Path::new("/games/Game");
+        let paths = names.iter().map(|name| folder.join(name)).collect();
+        sort_paths(paths, folder, slot)
+            .unwrap()
+            .iter()
+            .map(|path| relative_name(path, folder))
+            .collect()
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("Track 2.bin", "Track 10.bin"), Ordering::Less);
+        assert_eq!(natural_cmp("Track 02.bin", "Track 2.bin"), Ordering::Less);
+        assert_eq!(natural_cmp("track 3.bin", "Track 10.bin"), Ordering::Less);
+        assert_eq!(natural_cmp("Disc 9", "Disc 9 (Bonus)"), Ordering::Less);
+        assert_eq!(natural_cmp("a1b", "a1b"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_sort_paths_natural() {
+        let names = ["Track 1.bin", "Track 10.bin", "Track 11.bin", "Track 2.bin"];
+        assert_eq!(
+            sorted(&names, &slot(Sort::Natural, None)),
+            vec!["Track 1.bin", "Track 2.bin", "Track 10.bin", "Track 11.bin"]
+        );
+        assert_eq!(
+            sorted(&names, &slot(Sort::Ascending, None)),
+            Vec::from(names)
+        );
+    }
+
+    #[test]
+    fn test_sort_paths_regex() {
+        let names = [
+            "Game (Disc 10 of 12).bin",
+            "Game (Disc 2 of 12).bin",
+            "Game.bin",
+            "Game (Disc 1 of 12).bin",
+        ];
+        assert_eq!(
+            sorted(&names, &slot(Sort::Regex, Some(r"Disc (\d+)"))),
+            vec![
+                "Game (Disc 1 of 12).bin",
+                "Game (Disc 2 of 12).bin",
+                "Game (Disc 10 of 12).bin",
+                "Game.bin"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_paths_filename_regex_captures() {
+        let slot = InstancePackagerDataSlot {
+            filename_regex: Some(String::from(r"^(?P<name>.+) \(Track (?P<n>\d+)\)\.bin$")),
+            ..slot(Sort::Regex, None)
+        };
+        let names = [
+            "B (Track 10).bin",
+            "A (Track 2).bin",
+            "B (Track 9).bin",
+            "A (Track 1).bin",
+        ];
+        assert_eq!(
+            sorted(&names, &slot),
+            vec![
+                "A (Track 1).bin",
+                "A (Track 2).bin",
+                "B (Track 9).bin",
+                "B (Track 10).bin"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_paths_regex_errors() {
+        let folder = Path::new("/games/Game");
+        let paths = vec![folder.join("a.bin")];
+
+        let missing = sort_paths(paths.clone(), folder, &slot(Sort::Regex, None));
+        assert!(matches!(
+            missing,
+            Err(PackagerError::MissingSortRegex { slot_id: 1 })
+        ));
+
+        let invalid = sort_paths(paths, folder, &slot(Sort::Regex, Some("Disc (")));
+        assert!(matches!(invalid, Err(PackagerError::SortRegex { .. })));
+    }
+}
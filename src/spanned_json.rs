@@ -0,0 +1,367 @@
+//! A small JSON parser which keeps the line & column of every value and key,
+//! serde_json throws those away once parsing succeeds but `validate` needs them for its reports.
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    /// Kept as written so integers & floats can be told apart
+    Number(String),
+    String(String),
+    Array(Vec<Node>),
+    Object(Vec<Member>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Node {
+    pub(crate) value: Value,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Member {
+    pub(crate) key: String,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) value: Node,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ParseError {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) message: String,
+}
+
+impl Node {
+    pub(crate) fn type_name(&self) -> &'static str {
+        match &self.value {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::Number(_) => "a number",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Object(_) => "an object",
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_uint(&self) -> Option<usize> {
+        match &self.value {
+            Value::Number(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Node> {
+        match &self.value {
+            Value::Object(members) => members.iter().find(|m| m.key == key).map(|m| &m.value),
+            _ => None,
+        }
+    }
+}
+
+/// How deeply arrays & objects can be nested, the same as serde_json's limit.
+const MAX_DEPTH: usize = 128;
+
+pub(crate) fn parse(source: &str) -> Result<Node, ParseError> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        index: 0,
+        line: 1,
+        column: 1,
+        depth: 0,
+    };
+    parser.skip_whitespace();
+    let node = parser.value()?;
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.error("trailing characters after the JSON value"));
+    }
+    Ok(node)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+    /// How many arrays & objects the parser is inside
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: String::from(message),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.peek() == Some(expected) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{expected}`")))
+        }
+    }
+
+    fn value(&mut self) -> Result<Node, ParseError> {
+        let (line, column) = (self.line, self.column);
+        let value = match self.peek() {
+            Some('{') => self.nested(Parser::object)?,
+            Some('[') => self.nested(Parser::array)?,
+            Some('"') => Value::String(self.string()?),
+            Some('t') => self.literal("true", Value::Bool(true))?,
+            Some('f') => self.literal("false", Value::Bool(false))?,
+            Some('n') => self.literal("null", Value::Null)?,
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number()?,
+            Some(_) => return Err(self.error("expected a value")),
+            None => return Err(self.error("unexpected end of file")),
+        };
+        Ok(Node {
+            value,
+            line,
+            column,
+        })
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Parser) -> Result<Value, ParseError>,
+    ) -> Result<Value, ParseError> {
+        self.depth += 1;
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("recursion limit exceeded"));
+        }
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, ParseError> {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error("expected a value"));
+            }
+            self.next();
+        }
+        Ok(value)
+    }
+
+    /// `-? (0 | [1-9][0-9]*) (. [0-9]+)? ([eE] [+-]? [0-9]+)?`, anything else (like `01` or `1.`) is
+    /// rejected the same as serde_json would.
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let mut text = String::new();
+        if self.peek() == Some('-') {
+            text.push('-');
+            self.next();
+        }
+        match self.peek() {
+            Some('0') => {
+                text.push('0');
+                self.next();
+                if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    return Err(self.error("invalid number, leading zeros aren't allowed"));
+                }
+            }
+            Some(c) if c.is_ascii_digit() => self.digits(&mut text),
+            _ => return Err(self.error("invalid number")),
+        }
+        if self.peek() == Some('.') {
+            text.push('.');
+            self.next();
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("invalid number, expected a digit after `.`"));
+            }
+            self.digits(&mut text);
+        }
+        if let Some(e @ ('e' | 'E')) = self.peek() {
+            text.push(e);
+            self.next();
+            if let Some(sign @ ('+' | '-')) = self.peek() {
+                text.push(sign);
+                self.next();
+            }
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("invalid number, expected a digit in the exponent"));
+            }
+            self.digits(&mut text);
+        }
+        Ok(Value::Number(text))
+    }
+
+    fn digits(&mut self, text: &mut String) {
+        while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            text.push(c);
+            self.next();
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(result),
+                Some('\\') => match self.next() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('/') => result.push('/'),
+                    Some('b') => result.push('\u{8}'),
+                    Some('f') => result.push('\u{c}'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('u') => result.push(self.unicode_escape()?),
+                    _ => return Err(self.error("invalid escape")),
+                },
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("control character in string"))
+                }
+                Some(c) => result.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, ParseError> {
+        let high = self.hex4()?;
+        if (0xD800..0xDC00).contains(&high) {
+            if self.next() != Some('\\') || self.next() != Some('u') {
+                return Err(self.error("unpaired surrogate"));
+            }
+            let low = self.hex4()?;
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+            return char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"));
+        }
+        char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(Value::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, ParseError> {
+        self.expect('{')?;
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let (line, column) = (self.line, self.column);
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.value()?;
+            members.push(Member {
+                key,
+                line,
+                column,
+                value,
+            });
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(members)),
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_agrees_with_serde_json() {
+        let numbers = [
+            "0", "-0", "12", "-12", "1.5", "1e3", "1E+3", "1.5e-3", "01", "-01", "1.", ".5", "-",
+            "+1", "1e", "1e+", "0x1", "1.2.3",
+        ];
+        for number in numbers {
+            let source = format!("{{ \"id\": {number} }}");
+            assert_eq!(
+                parse(&source).is_ok(),
+                serde_json::from_str::<serde_json::Value>(&source).is_ok(),
+                "{source}"
+            );
+        }
+
+        for depth in [127, 128, 200_000] {
+            let source = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+            assert_eq!(
+                parse(&source).is_ok(),
+                serde_json::from_str::<serde_json::Value>(&source).is_ok(),
+                "{depth} nested arrays"
+            );
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::path::{Path, PathBuf};
+
+use crate::glob_stuff::{filename_regex, named_captures, relative_name};
+use crate::{DataSlot, InstancePackagerDataSlot, PackagerError};
+
+/// A piece of a `filename_template`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Part {
+    Text(String),
+    Placeholder {
+        value: Placeholder,
+        transforms: Vec<Transform>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Placeholder {
+    /// `{folder}`, the game folder's name
+    Folder,
+    /// `{parent}`, the name of the folder the game folder is in
+    Parent,
+    /// `{stem:<slot id>}`, the name without extension of the file given that slot id
+    Stem(usize),
+    /// `{ext:<slot id>}`, the extension of the file given that slot id
+    Ext(usize),
+    /// `{<name>}`, a named capture from one of the slots' `filename_regex`
+    Capture(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Transform {
+    Trim,
+    Title,
+    Upper,
+    Lower,
+}
+
+pub(crate) const TRANSFORMS: &[&str] = &["trim", "title", "upper", "lower"];
+
+/// Splits a template like `{parent} - {name|title}` into its parts, `{{` & `}}` are literal braces.
+///
+/// Placeholders can be followed by any number of `|transform`s, which are applied in order.
+pub(crate) fn parse(template: &str) -> Result<Vec<Part>, String> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest.find('}').ok_or_else(|| {
+                    String::from("a \"{\" is never closed, use \"{{\" for a brace")
+                })?;
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(parse_placeholder(&rest[..end])?);
+                chars = rest[end + 1..].chars();
+            }
+            '}' => return Err(String::from("unexpected \"}\", use \"}}\" for a brace")),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Part, String> {
+    let mut pieces = placeholder.split('|').map(str::trim);
+    let name = pieces.next().unwrap_or_default();
+
+    let slot_id = |argument: Option<&str>| {
+        argument
+            .and_then(|id| id.trim().parse().ok())
+            .ok_or_else(|| format!("\"{{{name}}}\" needs a slot id, e.g. \"{{{name}:100}}\""))
+    };
+    let (key, argument) = match name.split_once(':') {
+        Some((key, argument)) => (key.trim(), Some(argument)),
+        None => (name, None),
+    };
+    let value = match key {
+        "folder" => Placeholder::Folder,
+        "parent" => Placeholder::Parent,
+        "stem" => Placeholder::Stem(slot_id(argument)?),
+        "ext" => Placeholder::Ext(slot_id(argument)?),
+        "" => return Err(String::from("empty placeholder \"{}\"")),
+        capture if argument.is_none() => Placeholder::Capture(String::from(capture)),
+        _ => return Err(format!("only stem & ext take a slot id, not \"{key}\"")),
+    };
+
+    let transforms = pieces
+        .map(|transform| match transform {
+            "trim" => Ok(Transform::Trim),
+            "title" => Ok(Transform::Title),
+            "upper" => Ok(Transform::Upper),
+            "lower" => Ok(Transform::Lower),
+            unknown => Err(format!(
+                "unknown transform \"{unknown}\", expected one of {}",
+                TRANSFORMS.join(", ")
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Part::Placeholder { value, transforms })
+}
+
+/// What a game's placeholders are filled in from.
+pub(crate) struct Sources<'a> {
+    pub(crate) folder_path: &'a Path,
+    pub(crate) slots: &'a [InstancePackagerDataSlot],
+    pub(crate) slot_matches: &'a [Vec<PathBuf>],
+    /// The instance's slots, so `{stem:101}` is the file that ended up with id 101 after sorting
+    pub(crate) data_slots: &'a [DataSlot],
+}
+
+/// Characters exFAT can't store in a file name, besides the control characters.
+const FORBIDDEN_CHARS: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+/// Fills in a `filename_template` for a game, placeholders with nothing to fill them are left empty.
+///
+/// Path separators & the characters exFAT forbids are replaced with `_`, so the name can't reach into
+/// another folder or fail to be written to the SD card.
+pub(crate) fn render(template: &str, sources: &Sources) -> Result<String, PackagerError> {
+    let parts = parse(template).map_err(|message| PackagerError::FilenameTemplate {
+        template: String::from(template),
+        message,
+    })?;
+
+    let mut rendered = String::new();
+    for part in parts {
+        match part {
+            Part::Text(text) => rendered.push_str(&text),
+            Part::Placeholder { value, transforms } => {
+                let value = fill(&value, sources)?.unwrap_or_default();
+                rendered.push_str(&transforms.iter().fold(value, apply));
+            }
+        }
+    }
+    Ok(rendered
+        .chars()
+        .map(|c| match c {
+            c if c.is_control() || FORBIDDEN_CHARS.contains(&c) => '_',
+            c => c,
+        })
+        .collect())
+}
+
+fn fill(value: &Placeholder, sources: &Sources) -> Result<Option<String>, PackagerError> {
+    let file_name = |id: usize| {
+        sources
+            .data_slots
+            .iter()
+            .find(|slot| slot.id == id)
+            .map(|slot| PathBuf::from(&slot.filename))
+    };
+    let os_str = |s: Option<&std::ffi::OsStr>| s.map(|s| s.to_string_lossy().into_owned());
+
+    Ok(match value {
+        Placeholder::Folder => os_str(sources.folder_path.file_name()),
+        Placeholder::Parent => os_str(sources.folder_path.parent().and_then(Path::file_name)),
+        Placeholder::Stem(id) => file_name(*id).and_then(|path| os_str(path.file_stem())),
+        Placeholder::Ext(id) => file_name(*id).and_then(|path| os_str(path.extension())),
+        Placeholder::Capture(name) => {
+            for (slot, matched) in sources.slots.iter().zip(sources.slot_matches) {
+                let Some(regex) = filename_regex(slot)? else {
+                    continue;
+                };
+                let captured = matched.iter().find_map(|path| {
+                    named_captures(&regex, &relative_name(path, sources.folder_path))
+                        .into_iter()
+                        .find(|(group, _)| group == name)
+                });
+                if let Some((_, value)) = captured {
+                    return Ok(Some(value));
+                }
+            }
+            None
+        }
+    })
+}
+
+fn apply(value: String, transform: &Transform) -> String {
+    match transform {
+        Transform::Trim => value.split_whitespace().collect::<Vec<_>>().join(" "),
+        Transform::Upper => value.to_uppercase(),
+        Transform::Lower => value.to_lowercase(),
+        Transform::Title => value
+            .split(' ')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first
+                        .to_uppercase()
+                        .chain(chars.flat_map(char::to_lowercase))
+                        .collect(),
+                    None => String::new(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("{parent} - {{{stem:100|trim|title}}}"),
+            Ok(vec![
+                Part::Placeholder {
+                    value: Placeholder::Parent,
+                    transforms: vec![]
+                },
+                Part::Text(String::from(" - {")),
+                Part::Placeholder {
+                    value: Placeholder::Stem(100),
+                    transforms: vec![Transform::Trim, Transform::Title]
+                },
+                Part::Text(String::from("}")),
+            ])
+        );
+        assert!(parse("{stem}").unwrap_err().contains("needs a slot id"));
+        assert!(parse("{folder|shout}")
+            .unwrap_err()
+            .contains("unknown transform"));
+        assert!(parse("{folder").is_err());
+        assert!(parse("folder}").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let folder_path = Path::new("/common/arcade/street fighter ii");
+        let slots = vec![InstancePackagerDataSlot {
+            id: 100,
+            filename: String::from("*.zip"),
+            filename_regex: Some(String::from(r"^(?P<set>\w+)\.zip$")),
+            ..Default::default()
+        }];
+        let slot_matches = vec![vec![folder_path.join("sf2ce.zip")]];
+        let data_slots = vec![DataSlot {
+            id: 100,
+            filename: String::from("sf2ce.zip"),
+        }];
+        let sources = Sources {
+            folder_path,
+            slots: &slots,
+            slot_matches: &slot_matches,
+            data_slots: &data_slots,
+        };
+        let render = |template| render(template, &sources).unwrap();
+
+        assert_eq!(
+            render("{folder|title} ({set|upper}.{ext:100}) [{parent}]"),
+            "Street Fighter Ii (SF2CE.zip) [arcade]"
+        );
+        assert_eq!(
+            render("{stem:101}{missing}|{ folder |trim }"),
+            "_street fighter ii"
+        );
+        assert_eq!(
+            render("{parent}/{set}: \"{folder}\"?\t"),
+            "arcade_sf2ce_ _street fighter ii___"
+        );
+    }
+}
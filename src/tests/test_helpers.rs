@@ -7,7 +7,7 @@ pub(crate) fn make_fake_files(files: Vec<&str>) -> tempfile::TempDir {
 
     for file in files {
         let full_path = path.join(file);
-        create_dir_all(&full_path.parent().unwrap()).unwrap();
+        create_dir_all(full_path.parent().unwrap()).unwrap();
         File::create(full_path).unwrap();
     }
 
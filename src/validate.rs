@@ -0,0 +1,1437 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::glob_stuff::{relative_name, NameMatching};
+use crate::memory_writes::MAX_DISCS;
+use crate::override_keys::{self, OverrideKey};
+use crate::spanned_json::{self, Node, Value};
+use crate::template::{self, Part, Placeholder};
+use crate::{is_hidden, PackagerError, PACKAGER_NAME};
+
+/// JSON Schema for `instance-packager.json`, for editors & other tools.
+///
+/// `validate_config` checks the same rules, with line & column numbers in its reports.
+pub static CONFIG_SCHEMA: &str = include_str!("../instance-packager.schema.json");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The config won't load, or a setting in it will be ignored
+    Error,
+    /// Probably a mistake, but the config still works
+    Warning,
+}
+
+/// A problem found by `validate_config`, lines & columns start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: usize,
+    /// Where in the config the problem is, e.g. `overrides.wjammss.data_slots[1].sort`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}:{} {}: ", self.line, self.column, severity)?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Checks the contents of an `instance-packager.json` against `CONFIG_SCHEMA`, plus duplicate slot ids.
+///
+/// Returns every issue found in the order they appear in the file, an empty list means it's valid.
+pub fn validate_config(source: &str) -> Vec<ValidationIssue> {
+    match spanned_json::parse(source) {
+        Ok(root) => check_root(&root),
+        Err(err) => vec![ValidationIssue {
+            severity: Severity::Error,
+            line: err.line,
+            column: err.column,
+            path: String::new(),
+            message: err.message,
+        }],
+    }
+}
+
+/// Validates a core's `instance-packager.json`, also warning about overrides that don't match any
+/// folder in the core's asset folder & pattern overrides which are as good a match as each other.
+pub fn validate_core_config(
+    root_path: &Path,
+    core_name: &str,
+) -> Result<Vec<ValidationIssue>, PackagerError> {
+    let path = root_path.join("Cores").join(core_name).join(PACKAGER_NAME);
+    let source = fs::read_to_string(&path)
+        .map_err(|source| PackagerError::UnreadableConfig { path, source })?;
+
+    let Ok(root) = spanned_json::parse(&source) else {
+        return Ok(validate_config(&source));
+    };
+    let mut issues = check_root(&root);
+
+    if let (Some(platform_id), Some(overrides)) = (
+        root.get("platform_id").and_then(Node::as_str),
+        root.get("overrides"),
+    ) {
+        let asset_folder = root_path.join("Assets").join(platform_id).join("common");
+        let flag = |key| {
+            matches!(
+                root.get(key).map(|node| &node.value),
+                Some(Value::Bool(true))
+            )
+        };
+        let matching = NameMatching {
+            case_insensitive: flag("case_insensitive"),
+            normalize_unicode: flag("normalize_unicode"),
+        };
+        issues.extend(check_override_folders(overrides, &asset_folder, matching));
+        issues.sort_by_key(|issue| (issue.line, issue.column));
+    }
+    Ok(issues)
+}
+
+fn check_override_folders(
+    overrides: &Node,
+    asset_folder: &Path,
+    matching: NameMatching,
+) -> Vec<ValidationIssue> {
+    let Value::Object(members) = &overrides.value else {
+        return vec![];
+    };
+
+    // relative to the asset folder, which both name & path keys can be matched against
+    let mut folders: Vec<String> = WalkDir::new(asset_folder)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !is_hidden(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir())
+        .map(|e| relative_name(e.path(), asset_folder))
+        .collect();
+    folders.sort();
+
+    let mut keys: Vec<OverrideKey> = members
+        .iter()
+        .filter_map(|member| OverrideKey::parse(&member.key, matching).ok())
+        .collect();
+    keys.sort_by(|a, b| a.key.cmp(&b.key));
+    let warning = |key: &str, message: String| {
+        let member = members.iter().find(|member| member.key == key)?;
+        Some(ValidationIssue {
+            severity: Severity::Warning,
+            line: member.line,
+            column: member.column,
+            path: format!("overrides.{}", member.key),
+            message,
+        })
+    };
+
+    let mut issues = vec![];
+    for key in &keys {
+        if !folders.iter().any(|folder| key.matches(folder)) {
+            let message = if key.is_pattern() {
+                format!("no folder in {} matches it", asset_folder.display())
+            } else if key.path {
+                format!("no folder at \"{}\" in {}", key.key, asset_folder.display())
+            } else {
+                format!(
+                    "no folder called \"{}\" in {}",
+                    key.key,
+                    asset_folder.display()
+                )
+            };
+            issues.extend(warning(&key.key, message));
+        }
+    }
+
+    let mut ambiguous: HashSet<(&str, &str)> = HashSet::new();
+    for folder in &folders {
+        let matched = override_keys::matching(&keys, folder);
+        let [used, next, ..] = matched.as_slice() else {
+            continue;
+        };
+        // exact keys only tie when `case_insensitive` or `normalize_unicode` make two of them the same
+        if used.rank(folder) == next.rank(folder) && ambiguous.insert((&used.key, &next.key)) {
+            let message = format!(
+                "\"{}\" & \"{}\" match \"{folder}\" equally well, \"{}\" is used",
+                used.key, next.key, used.key
+            );
+            issues.extend(warning(&next.key, message));
+        }
+    }
+    issues
+}
+
+type Check = fn(&mut Checker, &Node, &str);
+
+struct Field {
+    name: &'static str,
+    required: bool,
+    check: Check,
+}
+
+const fn required(name: &'static str, check: Check) -> Field {
+    Field {
+        name,
+        required: true,
+        check,
+    }
+}
+
+const fn optional(name: &'static str, check: Check) -> Field {
+    Field {
+        name,
+        required: false,
+        check,
+    }
+}
+
+const ROOT_FIELDS: &[Field] = &[
+    optional("$schema", Checker::string),
+    required("output", Checker::string),
+    required("platform_id", Checker::string),
+    required("data_slots", Checker::data_slots),
+    optional("memory_writes", Checker::memory_writes),
+    optional("core_select", Checker::select),
+    optional("variant_select", Checker::select),
+    optional("slot_limit", Checker::slot_limit),
+    optional("overrides", Checker::overrides),
+    optional("multi_disc", Checker::multi_disc),
+    optional("filename_template", Checker::filename_template),
+    optional("exclude", Checker::globs),
+    optional("case_insensitive", Checker::boolean),
+    optional("normalize_unicode", Checker::boolean),
+];
+
+const DATA_SLOT_FIELDS: &[Field] = &[
+    required("id", Checker::uint),
+    required("filename", Checker::string),
+    optional("filename_regex", Checker::regex),
+    required("sort", Checker::sort),
+    required("required", Checker::boolean),
+    optional("as_filename", Checker::boolean),
+    optional("sort_regex", Checker::regex),
+    optional("primary", Checker::boolean),
+    optional("max_count", Checker::positive_uint),
+    optional("id_stride", Checker::positive_uint),
+    optional("exclude", Checker::globs),
+];
+
+const SORT_VALUES: &[&str] = &[
+    "single",
+    "ascending",
+    "descending",
+    "natural",
+    "regex",
+    "cue",
+];
+
+const OVERRIDE_FIELDS: &[Field] = &[
+    optional("filename", Checker::string),
+    optional("filename_template", Checker::filename_template),
+    optional("data_slots", Checker::data_slots),
+    optional("merge", Checker::merge),
+    optional("remove_slots", Checker::slot_ids),
+    optional("memory_writes", Checker::memory_writes),
+    optional("core_select", Checker::select),
+    optional("variant_select", Checker::select),
+];
+
+const MERGE_FIELDS: &[Field] = &[
+    optional("data_slots", Checker::merge_mode),
+    optional("memory_writes", Checker::merge_mode),
+];
+
+const MERGE_MODES: &[&str] = &["replace", "append", "patch"];
+
+const MEMORY_WRITE_FIELDS: &[Field] = &[
+    required("address", Checker::uint_or_string),
+    required("data", Checker::memory_data),
+];
+
+const COMPUTED_DATA_FIELDS: &[Field] = &[
+    optional("size", Checker::uint),
+    optional("count", Checker::uint),
+    optional("crc32", Checker::uint),
+    optional("lookup", Checker::lookup),
+    optional("default", Checker::uint_or_string),
+];
+
+const COMPUTED_DATA_KINDS: &[&str] = &["size", "count", "crc32", "lookup"];
+
+const SELECT_FIELDS: &[Field] = &[
+    required("id", Checker::uint),
+    required("select", Checker::boolean),
+];
+
+const MULTI_DISC_FIELDS: &[Field] = &[
+    required("mode", Checker::multi_disc_mode),
+    optional("id_offset", Checker::uint),
+];
+
+const MULTI_DISC_MODES: &[&str] = &["subfolder", "combined"];
+
+const SLOT_LIMIT_FIELDS: &[Field] = &[
+    required("count", Checker::uint),
+    required("message", Checker::string),
+];
+
+/// The ids a data slot's files can be given.
+struct SlotIds {
+    id: usize,
+    stride: usize,
+    /// `None` when the slot can take any number of files
+    count: Option<usize>,
+}
+
+impl SlotIds {
+    fn from_node(slot: &Node) -> Option<SlotIds> {
+        let id = slot.get("id")?.as_uint()?;
+        let stride = match slot.get("id_stride") {
+            Some(stride) => stride.as_uint().filter(|stride| *stride > 0)?,
+            None => 1,
+        };
+        let single = slot.get("sort").and_then(Node::as_str) == Some("single");
+        let count = match slot.get("max_count") {
+            Some(max_count) => Some(max_count.as_uint().filter(|count| *count > 0)?),
+            None => single.then_some(1),
+        };
+        Some(SlotIds { id, stride, count })
+    }
+
+    /// The id the slot's `count`th file gets, `None` if it's too big to be an id.
+    fn nth_id(&self, count: usize) -> Option<usize> {
+        (count - 1)
+            .checked_mul(self.stride)
+            .and_then(|offset| self.id.checked_add(offset))
+    }
+
+    fn contains(&self, id: usize) -> bool {
+        let Some(offset) = id.checked_sub(self.id) else {
+            return false;
+        };
+        offset % self.stride == 0 && self.count.is_none_or(|count| offset / self.stride < count)
+    }
+}
+
+fn check_root(root: &Node) -> Vec<ValidationIssue> {
+    let mut checker = Checker { issues: vec![] };
+    checker.object(root, "", ROOT_FIELDS);
+    checker.template_placeholders(root);
+    checker.override_merges(root);
+    checker.disc_id_offset(root);
+    checker.computed_write_slots(root);
+    checker
+        .issues
+        .sort_by_key(|issue| (issue.line, issue.column));
+    checker.issues
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        String::from(key)
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+struct Checker {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Checker {
+    fn error(&mut self, line: usize, column: usize, path: &str, message: String) {
+        self.issue(Severity::Error, line, column, path, message);
+    }
+
+    fn warning(&mut self, line: usize, column: usize, path: &str, message: String) {
+        self.issue(Severity::Warning, line, column, path, message);
+    }
+
+    fn issue(
+        &mut self,
+        severity: Severity,
+        line: usize,
+        column: usize,
+        path: &str,
+        message: String,
+    ) {
+        self.issues.push(ValidationIssue {
+            severity,
+            line,
+            column,
+            path: String::from(path),
+            message,
+        });
+    }
+
+    fn wrong_type(&mut self, node: &Node, path: &str, expected: &str) {
+        let message = format!("expected {expected}, found {}", node.type_name());
+        self.error(node.line, node.column, path, message);
+    }
+
+    fn object(&mut self, node: &Node, path: &str, fields: &[Field]) {
+        let Value::Object(members) = &node.value else {
+            return self.wrong_type(node, path, "an object");
+        };
+
+        let mut seen = HashSet::new();
+        for member in members {
+            let member_path = join_path(path, &member.key);
+            if !seen.insert(member.key.as_str()) {
+                let message = format!("duplicate key \"{}\"", member.key);
+                self.error(member.line, member.column, &member_path, message);
+                continue;
+            }
+
+            match fields.iter().find(|field| field.name == member.key) {
+                Some(field) => (field.check)(self, &member.value, &member_path),
+                None => {
+                    let message = unknown_key_message(&member.key, fields);
+                    self.error(member.line, member.column, &member_path, message);
+                }
+            }
+        }
+
+        for field in fields.iter().filter(|f| f.required) {
+            if !seen.contains(field.name) {
+                let message = format!("missing required key \"{}\"", field.name);
+                self.error(node.line, node.column, path, message);
+            }
+        }
+    }
+
+    fn array<'a>(&mut self, node: &'a Node, path: &str) -> &'a [Node] {
+        match &node.value {
+            Value::Array(items) => items,
+            _ => {
+                self.wrong_type(node, path, "an array");
+                &[]
+            }
+        }
+    }
+
+    fn string(&mut self, node: &Node, path: &str) {
+        if node.as_str().is_none() {
+            self.wrong_type(node, path, "a string");
+        }
+    }
+
+    fn boolean(&mut self, node: &Node, path: &str) {
+        if !matches!(node.value, Value::Bool(_)) {
+            self.wrong_type(node, path, "true or false");
+        }
+    }
+
+    fn uint(&mut self, node: &Node, path: &str) {
+        if node.as_uint().is_none() {
+            self.wrong_type(node, path, "a whole number of 0 or more");
+        }
+    }
+
+    fn positive_uint(&mut self, node: &Node, path: &str) {
+        if node.as_uint().is_none_or(|n| n == 0) {
+            self.wrong_type(node, path, "a whole number of 1 or more");
+        }
+    }
+
+    fn uint_or_string(&mut self, node: &Node, path: &str) {
+        if node.as_uint().is_none() && node.as_str().is_none() {
+            self.wrong_type(node, path, "a whole number or a string");
+        }
+    }
+
+    fn one_of(&mut self, node: &Node, path: &str, what: &str, allowed: &[&str]) {
+        match node.as_str() {
+            Some(value) if allowed.contains(&value) => {}
+            Some(value) => {
+                let message = format!(
+                    "unknown {what} \"{value}\", expected one of {}",
+                    allowed.join(", ")
+                );
+                self.error(node.line, node.column, path, message);
+            }
+            None => self.wrong_type(node, path, "a string"),
+        }
+    }
+
+    fn sort(&mut self, node: &Node, path: &str) {
+        self.one_of(node, path, "sort", SORT_VALUES);
+    }
+
+    fn multi_disc_mode(&mut self, node: &Node, path: &str) {
+        self.one_of(node, path, "mode", MULTI_DISC_MODES);
+    }
+
+    fn merge_mode(&mut self, node: &Node, path: &str) {
+        self.one_of(node, path, "merge mode", MERGE_MODES);
+    }
+
+    fn merge(&mut self, node: &Node, path: &str) {
+        self.object(node, path, MERGE_FIELDS);
+    }
+
+    fn slot_ids(&mut self, node: &Node, path: &str) {
+        for (index, id) in self.array(node, path).iter().enumerate() {
+            self.uint(id, &format!("{path}[{index}]"));
+        }
+    }
+
+    fn globs(&mut self, node: &Node, path: &str) {
+        for (index, glob) in self.array(node, path).iter().enumerate() {
+            let glob_path = format!("{path}[{index}]");
+            match glob
+                .as_str()
+                .map(|glob| glob::Pattern::new(glob.trim_end_matches('/')))
+            {
+                Some(Ok(_)) => {}
+                Some(Err(err)) => {
+                    self.error(glob.line, glob.column, &glob_path, String::from(err.msg))
+                }
+                None => self.wrong_type(glob, &glob_path, "a string"),
+            }
+        }
+    }
+
+    fn regex(&mut self, node: &Node, path: &str) {
+        match node.as_str().map(regex::Regex::new) {
+            Some(Ok(_)) => {}
+            Some(Err(err)) => self.error(node.line, node.column, path, err.to_string()),
+            None => self.wrong_type(node, path, "a string"),
+        }
+    }
+
+    fn data_slots(&mut self, node: &Node, path: &str) {
+        let mut ids: HashMap<usize, usize> = HashMap::new();
+        let mut primary: Option<usize> = None;
+        for (index, slot) in self.array(node, path).iter().enumerate() {
+            let slot_path = format!("{path}[{index}]");
+            self.object(slot, &slot_path, DATA_SLOT_FIELDS);
+            self.sort_regex_matches_sort(slot, &slot_path);
+
+            if let Some(
+                node @ Node {
+                    value: Value::Bool(true),
+                    ..
+                },
+            ) = slot.get("primary")
+            {
+                match primary {
+                    Some(first) => {
+                        let message =
+                            format!("only one slot can be primary, {path}[{first}] already is");
+                        self.error(
+                            node.line,
+                            node.column,
+                            &join_path(&slot_path, "primary"),
+                            message,
+                        );
+                    }
+                    None => primary = Some(index),
+                }
+            }
+
+            let Some(id_node) = slot.get("id") else {
+                continue;
+            };
+            if let Some(id) = id_node.as_uint() {
+                if let Some(first) = ids.insert(id, index) {
+                    let message = format!("slot id {id} is already used by {path}[{first}]");
+                    self.error(
+                        id_node.line,
+                        id_node.column,
+                        &join_path(&slot_path, "id"),
+                        message,
+                    );
+                    ids.insert(id, first);
+                }
+            }
+        }
+        self.slot_id_ranges(node, path);
+    }
+
+    /// Warns about slots whose ids can run into another slot's, before a game with enough files does.
+    fn slot_id_ranges(&mut self, node: &Node, path: &str) {
+        let Value::Array(slots) = &node.value else {
+            return;
+        };
+        let ranges: Vec<(usize, SlotIds)> = slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, SlotIds::from_node(slot)?)))
+            .collect();
+
+        for (index, range) in &ranges {
+            // unbounded slots only need room for a second file to be usable
+            let last_id = range.nth_id(range.count.unwrap_or(2));
+            if last_id.is_none() {
+                let slot = &slots[*index];
+                let message = String::from(
+                    "\"id_stride\" is too big, the ids given to the slot's files would overflow",
+                );
+                self.error(slot.line, slot.column, &format!("{path}[{index}]"), message);
+                continue;
+            }
+
+            // only the nearest, the rest would be reached after it
+            let reached = ranges
+                .iter()
+                .filter(|(_, other)| other.id != range.id && range.contains(other.id))
+                .min_by_key(|(_, other)| other.id);
+            if let Some((other_index, other)) = reached {
+                let slot = &slots[*index];
+                let message = match (range.count, last_id) {
+                    (Some(_), Some(last_id)) => format!(
+                        "takes ids {} to {last_id}, which includes {}, the id of {path}[{other_index}]",
+                        range.id, other.id
+                    ),
+                    _ => format!(
+                        "takes ids from {} up, a game with enough files would reach {}, the id of {path}[{other_index}], set \"max_count\" to stop it",
+                        range.id, other.id
+                    ),
+                };
+                self.warning(slot.line, slot.column, &format!("{path}[{index}]"), message);
+            }
+        }
+    }
+
+    fn sort_regex_matches_sort(&mut self, slot: &Node, path: &str) {
+        let is_regex = slot.get("sort").and_then(Node::as_str) == Some("regex");
+        match (is_regex, slot.get("sort_regex")) {
+            (true, None) if slot.get("filename_regex").is_none() => {
+                let message = String::from(
+                    "missing \"sort_regex\" (or \"filename_regex\"), needed for the regex sort",
+                );
+                self.error(slot.line, slot.column, path, message);
+            }
+            (false, Some(sort_regex)) => {
+                let message = String::from("only used when \"sort\" is \"regex\"");
+                let path = join_path(path, "sort_regex");
+                self.error(sort_regex.line, sort_regex.column, &path, message);
+            }
+            _ => {}
+        }
+    }
+
+    fn memory_writes(&mut self, node: &Node, path: &str) {
+        for (index, write) in self.array(node, path).iter().enumerate() {
+            self.object(write, &format!("{path}[{index}]"), MEMORY_WRITE_FIELDS);
+        }
+    }
+
+    fn memory_data(&mut self, node: &Node, path: &str) {
+        if !matches!(node.value, Value::Object(_)) {
+            if node.as_uint().is_none() && node.as_str().is_none() {
+                self.wrong_type(node, path, "a whole number, a string or an object");
+            }
+            return;
+        }
+        self.object(node, path, COMPUTED_DATA_FIELDS);
+
+        let kinds: Vec<&str> = COMPUTED_DATA_KINDS
+            .iter()
+            .copied()
+            .filter(|kind| node.get(kind).is_some())
+            .collect();
+        if kinds.len() != 1 {
+            let message = format!("expected exactly one of {}", COMPUTED_DATA_KINDS.join(", "));
+            self.error(node.line, node.column, path, message);
+        } else if kinds != ["lookup"] && node.get("default").is_some() {
+            let message = format!(
+                "\"default\" is only used by \"lookup\", not \"{}\"",
+                kinds[0]
+            );
+            self.error(node.line, node.column, path, message);
+        }
+    }
+
+    fn lookup(&mut self, node: &Node, path: &str) {
+        let Value::Object(members) = &node.value else {
+            return self.wrong_type(node, path, "an object");
+        };
+        for member in members {
+            self.uint_or_string(&member.value, &join_path(path, &member.key));
+        }
+    }
+
+    fn select(&mut self, node: &Node, path: &str) {
+        self.object(node, path, SELECT_FIELDS);
+    }
+
+    fn multi_disc(&mut self, node: &Node, path: &str) {
+        self.object(node, path, MULTI_DISC_FIELDS);
+
+        let combined = node.get("mode").and_then(Node::as_str) == Some("combined");
+        match (combined, node.get("id_offset")) {
+            (true, None) => {
+                let message = String::from("missing \"id_offset\", needed for the combined mode");
+                self.error(node.line, node.column, path, message);
+            }
+            (false, Some(id_offset)) => {
+                let message = String::from("only used when \"mode\" is \"combined\"");
+                let path = join_path(path, "id_offset");
+                self.error(id_offset.line, id_offset.column, &path, message);
+            }
+            _ => {}
+        }
+    }
+
+    /// Warns when the combined mode's `id_offset` doesn't leave room for every id one disc can take.
+    fn disc_id_offset(&mut self, root: &Node) {
+        let Some(multi_disc) = root.get("multi_disc") else {
+            return;
+        };
+        let (Some("combined"), Some(id_offset)) = (
+            multi_disc.get("mode").and_then(Node::as_str),
+            multi_disc.get("id_offset"),
+        ) else {
+            return;
+        };
+        let (Some(offset), Some(Value::Array(slots))) = (
+            id_offset.as_uint(),
+            root.get("data_slots").map(|slots| &slots.value),
+        ) else {
+            return;
+        };
+        let ranges: Vec<(usize, SlotIds)> = slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, SlotIds::from_node(slot)?)))
+            .collect();
+        let Some(first_id) = ranges.iter().map(|(_, range)| range.id).min() else {
+            return;
+        };
+        let Some(second_disc) = first_id.checked_add(offset) else {
+            let message = String::from("too big, disc 2's ids would overflow");
+            let path = "multi_disc.id_offset";
+            return self.error(id_offset.line, id_offset.column, path, message);
+        };
+
+        let unbounded = ranges.iter().find(|(_, range)| range.count.is_none());
+        let message = match unbounded {
+            Some((index, range)) => format!(
+                "data_slots[{index}] takes ids from {} up, a disc with enough files would reach {second_disc}, where disc 2's ids start, set \"max_count\" on it",
+                range.id
+            ),
+            None => {
+                let last_id = ranges
+                    .iter()
+                    .filter_map(|(_, range)| range.nth_id(range.count?))
+                    .max()
+                    .unwrap_or(first_id);
+                if last_id < second_disc {
+                    return;
+                }
+                format!(
+                    "a disc's slots take ids {first_id} to {last_id}, but disc 2's start at {second_disc}, it needs to be at least {}",
+                    last_id - first_id + 1
+                )
+            }
+        };
+        let path = "multi_disc.id_offset";
+        self.warning(id_offset.line, id_offset.column, path, message);
+    }
+
+    /// Warns about `size`, `count` & `crc32` memory writes reading a slot id none of the data slots
+    /// (root or override) give out, they'd be left out of every instance.
+    fn computed_write_slots(&mut self, root: &Node) {
+        let overrides: Vec<(String, &Node)> = match root.get("overrides") {
+            Some(Node {
+                value: Value::Object(members),
+                ..
+            }) => members
+                .iter()
+                .map(|member| (join_path("overrides", &member.key), &member.value))
+                .collect(),
+            _ => vec![],
+        };
+        let configs: Vec<(String, &Node)> = std::iter::once((String::new(), root))
+            .chain(overrides)
+            .collect();
+
+        let slots: Vec<&Node> = configs
+            .iter()
+            .filter_map(|(_, config)| match config.get("data_slots") {
+                Some(Node {
+                    value: Value::Array(slots),
+                    ..
+                }) => Some(slots),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let ranges: Vec<SlotIds> = slots
+            .iter()
+            .filter_map(|slot| SlotIds::from_node(slot))
+            .collect();
+        // a slot that can't be read has already been reported, & might be the one a write reads
+        if ranges.len() != slots.len() {
+            return;
+        }
+        let id_offset = root
+            .get("multi_disc")
+            .filter(|multi_disc| multi_disc.get("mode").and_then(Node::as_str) == Some("combined"))
+            .and_then(|multi_disc| multi_disc.get("id_offset"))
+            .and_then(Node::as_uint)
+            .filter(|offset| *offset > 0);
+
+        for (path, config) in configs {
+            let Some(Node {
+                value: Value::Array(writes),
+                ..
+            }) = config.get("memory_writes")
+            else {
+                continue;
+            };
+            for (index, write) in writes.iter().enumerate() {
+                let Some(data) = write.get("data") else {
+                    continue;
+                };
+                let kinds: Vec<(&str, &Node)> = ["size", "count", "crc32"]
+                    .into_iter()
+                    .filter_map(|kind| Some((kind, data.get(kind)?)))
+                    .collect();
+                let [(kind, id_node)] = kinds[..] else {
+                    continue;
+                };
+                let Some(id) = id_node.as_uint() else {
+                    continue;
+                };
+                let declared = if kind == "count" {
+                    ranges.iter().any(|range| range.id == id)
+                } else {
+                    // disc n's files are given ids (n - 1) * id_offset up from disc 1's
+                    (0..MAX_DISCS)
+                        .map_while(|disc| match id_offset {
+                            Some(offset) => id.checked_sub(disc.checked_mul(offset)?),
+                            None => (disc == 0).then_some(id),
+                        })
+                        .any(|id| ranges.iter().any(|range| range.contains(id)))
+                };
+                if !declared {
+                    let path = join_path(&path, &format!("memory_writes[{index}].data.{kind}"));
+                    let message =
+                        format!("no data slot gives out the id {id}, the write is always left out");
+                    self.warning(id_node.line, id_node.column, &path, message);
+                }
+            }
+        }
+    }
+
+    fn filename_template(&mut self, node: &Node, path: &str) {
+        match node.as_str().map(template::parse) {
+            Some(Ok(_)) => {}
+            Some(Err(message)) => self.error(node.line, node.column, path, message),
+            None => self.wrong_type(node, path, "a string"),
+        }
+    }
+
+    /// Checks the root & override templates only use slot ids & captures from the data slots they go with.
+    fn template_placeholders(&mut self, root: &Node) {
+        let root_slots = root.get("data_slots");
+        if let Some(template) = root.get("filename_template") {
+            self.template_matches_slots(template, "filename_template", root_slots);
+        }
+
+        let Some(Node {
+            value: Value::Object(overrides),
+            ..
+        }) = root.get("overrides")
+        else {
+            return;
+        };
+        for member in overrides {
+            if let Some(template) = member.value.get("filename_template") {
+                let path = format!("overrides.{}.filename_template", member.key);
+                let slots = member.value.get("data_slots").or(root_slots);
+                self.template_matches_slots(template, &path, slots);
+            }
+        }
+    }
+
+    fn template_matches_slots(&mut self, template: &Node, path: &str, slots: Option<&Node>) {
+        let Some(Ok(parts)) = template.as_str().map(template::parse) else {
+            return;
+        };
+        let slots = match slots {
+            Some(Node {
+                value: Value::Array(slots),
+                ..
+            }) => slots.as_slice(),
+            _ => &[],
+        };
+        // slots taking several files use the ids after theirs too
+        let ids: Vec<(usize, bool)> = slots
+            .iter()
+            .filter_map(|slot| {
+                let id = slot.get("id").and_then(Node::as_uint)?;
+                let single = slot.get("sort").and_then(Node::as_str) == Some("single");
+                Some((id, single))
+            })
+            .collect();
+        let captures: Vec<String> = slots
+            .iter()
+            .filter_map(|slot| slot.get("filename_regex").and_then(Node::as_str))
+            .filter_map(|pattern| regex::Regex::new(pattern).ok())
+            .flat_map(|regex| {
+                let names = regex.capture_names().flatten().map(String::from);
+                names.collect::<Vec<_>>()
+            })
+            .collect();
+
+        for part in parts {
+            let message = match part {
+                Part::Placeholder {
+                    value: Placeholder::Stem(id) | Placeholder::Ext(id),
+                    ..
+                } if !ids
+                    .iter()
+                    .any(|&(slot_id, single)| slot_id == id || (!single && slot_id < id)) =>
+                {
+                    format!("no data slot has the id {id}")
+                }
+                Part::Placeholder {
+                    value: Placeholder::Capture(name),
+                    ..
+                } if !captures.contains(&name) => {
+                    format!("\"{{{name}}}\" isn't a placeholder or a named capture in any filename_regex")
+                }
+                _ => continue,
+            };
+            self.error(template.line, template.column, path, message);
+        }
+    }
+
+    /// Checks appended slots don't reuse root slot ids & removed slots are in the root.
+    fn override_merges(&mut self, root: &Node) {
+        let (
+            Some(Node {
+                value: Value::Object(overrides),
+                ..
+            }),
+            Some(Node {
+                value: Value::Array(root_slots),
+                ..
+            }),
+        ) = (root.get("overrides"), root.get("data_slots"))
+        else {
+            return;
+        };
+        let root_ids: Vec<usize> = root_slots
+            .iter()
+            .filter_map(|slot| slot.get("id").and_then(Node::as_uint))
+            .collect();
+
+        for member in overrides {
+            let path = join_path("overrides", &member.key);
+            let removed: Vec<&Node> = match member.value.get("remove_slots") {
+                Some(Node {
+                    value: Value::Array(ids),
+                    ..
+                }) => ids.iter().collect(),
+                _ => vec![],
+            };
+            for (index, id_node) in removed.iter().enumerate() {
+                if let Some(id) = id_node.as_uint().filter(|id| !root_ids.contains(id)) {
+                    let path = format!("{path}.remove_slots[{index}]");
+                    let message = format!("no root data slot has the id {id}");
+                    self.warning(id_node.line, id_node.column, &path, message);
+                }
+            }
+
+            let appending = member
+                .value
+                .get("merge")
+                .and_then(|merge| merge.get("data_slots"))
+                .and_then(Node::as_str)
+                == Some("append");
+            let Some(Node {
+                value: Value::Array(slots),
+                ..
+            }) = member.value.get("data_slots").filter(|_| appending)
+            else {
+                continue;
+            };
+            for (index, slot) in slots.iter().enumerate() {
+                let Some(id_node) = slot.get("id") else {
+                    continue;
+                };
+                let still_in_root = id_node.as_uint().is_some_and(|id| {
+                    root_ids.contains(&id) && !removed.iter().any(|r| r.as_uint() == Some(id))
+                });
+                if still_in_root {
+                    let path = format!("{path}.data_slots[{index}].id");
+                    let message = String::from(
+                        "the root data_slots already use this id, \"patch\" would replace that slot",
+                    );
+                    self.error(id_node.line, id_node.column, &path, message);
+                }
+            }
+        }
+    }
+
+    fn slot_limit(&mut self, node: &Node, path: &str) {
+        self.object(node, path, SLOT_LIMIT_FIELDS);
+    }
+
+    fn overrides(&mut self, node: &Node, path: &str) {
+        let Value::Object(members) = &node.value else {
+            return self.wrong_type(node, path, "an object");
+        };
+        for member in members {
+            let member_path = join_path(path, &member.key);
+            if let Err(message) = OverrideKey::parse(&member.key, NameMatching::default()) {
+                let message = format!("invalid key: {message}");
+                self.error(member.line, member.column, &member_path, message);
+            }
+            self.object(&member.value, &member_path, OVERRIDE_FIELDS);
+        }
+    }
+}
+
+/// Points out the likely intended key for typos like `file_name` or `Filename`.
+fn unknown_key_message(key: &str, fields: &[Field]) -> String {
+    let simplify = |s: &str| s.replace(['_', '-'], "").to_lowercase();
+    match fields
+        .iter()
+        .find(|field| simplify(field.name) == simplify(key))
+    {
+        Some(field) => format!("unknown key \"{key}\", did you mean \"{}\"?", field.name),
+        None => format!(
+            "unknown key \"{key}\", expected one of {}",
+            fields.iter().map(|f| f.name).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue_at(issues: &[ValidationIssue], line: usize) -> &ValidationIssue {
+        issues
+            .iter()
+            .find(|issue| issue.line == line)
+            .unwrap_or_else(|| panic!("no issue on line {line} in {issues:?}"))
+    }
+
+    #[test]
+    fn test_validate_config_valid() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/ng/Mazamars312.NeoGeo",
+  "platform_id": "ng",
+  "slot_limit": { "count": 27, "message": "Too many" },
+  "data_slots": [
+    { "id": 3, "filename": "srom", "sort": "single", "required": true },
+    { "id": 4, "filename": "*.bin", "sort": "ascending", "required": false, "as_filename": true, "max_count": 6 }
+  ],
+  "memory_writes": [{ "address": "0x00000004", "data": 12 }],
+  "core_select": { "id": 1, "select": true },
+  "variant_select": { "id": 2, "select": false },
+  "multi_disc": { "mode": "combined", "id_offset": 10 },
+  "overrides": { "wjammss": { "filename": "Windjammers" } }
+}"#,
+        );
+        assert_eq!(issues, vec![]);
+    }
+
+    #[test]
+    fn test_validate_config_mistakes() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/ng/Mazamars312.NeoGeo",
+  "platform_id": 12,
+  "data_slots": [
+    { "id": 3, "filename": "srom", "sort": "single", "required": true },
+    { "id": 3, "filename": "prom", "sort": "alphabetical", "required": true }
+  ],
+  "overrides": {
+    "wjammss": {
+      "file_name": "Windjammers"
+    }
+  }
+}"#,
+        );
+        assert_eq!(issues.len(), 4);
+
+        let wrong_type = issue_at(&issues, 3);
+        assert_eq!(wrong_type.column, 18);
+        assert_eq!(wrong_type.path, "platform_id");
+        assert_eq!(wrong_type.message, "expected a string, found a number");
+
+        let duplicate = &issues[1];
+        assert_eq!((duplicate.line, duplicate.column), (6, 13));
+        assert_eq!(duplicate.path, "data_slots[1].id");
+        assert!(duplicate.message.contains("data_slots[0]"));
+
+        let sort = &issues[2];
+        assert_eq!((sort.line, sort.column), (6, 44));
+        assert!(sort.message.starts_with("unknown sort \"alphabetical\""));
+
+        let unknown = issue_at(&issues, 10);
+        assert_eq!(unknown.column, 7);
+        assert_eq!(unknown.path, "overrides.wjammss.file_name");
+        assert_eq!(
+            unknown.message,
+            "unknown key \"file_name\", did you mean \"filename\"?"
+        );
+    }
+
+    #[test]
+    fn test_validate_config_missing_keys_and_syntax() {
+        let issues = validate_config("{\n  \"output\": \"Assets/abc\"\n}");
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().all(|i| (i.line, i.column) == (1, 1)));
+        assert!(issues[0].message.contains("platform_id"));
+
+        let issues = validate_config("{\n  \"output\": \"Assets/abc\",\n}");
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].line, issues[0].column), (3, 1));
+    }
+
+    #[test]
+    fn test_validate_config_sort_regex() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/pcecd/core",
+  "platform_id": "pcecd",
+  "data_slots": [
+    { "id": 10, "filename": "*.bin", "sort": "regex", "required": true, "max_count": 9 },
+    { "id": 20, "filename": "*.cue", "sort": "natural", "required": true, "sort_regex": "(\\d+)", "max_count": 9 },
+    { "id": 60, "filename": "*.bin", "filename_regex": "Disc (\\d+)", "sort": "regex", "required": true },
+    { "id": 30, "filename": "*.iso", "sort": "regex", "required": true, "sort_regex": "Disc (", "max_count": 9 },
+    { "id": 40, "filename": "*.cue", "sort": "single", "required": true, "primary": true },
+    { "id": 50, "filename": "*.chd", "sort": "single", "required": true, "primary": true }
+  ]
+}"#,
+        );
+        assert_eq!(issues.len(), 4);
+        assert_eq!((issues[0].line, issues[0].column), (5, 5));
+        assert!(issues[0].message.contains("missing \"sort_regex\""));
+        assert_eq!(issues[1].path, "data_slots[1].sort_regex");
+        assert_eq!(issues[2].path, "data_slots[3].sort_regex");
+        assert_eq!(issues[3].path, "data_slots[5].primary");
+        assert!(issues[3].message.contains("data_slots[4]"));
+    }
+
+    #[test]
+    fn test_validate_config_slot_id_ranges() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/pcecd/core",
+  "platform_id": "pcecd",
+  "data_slots": [
+    { "id": 100, "filename": "*.cue", "sort": "single", "required": true },
+    { "id": 101, "filename": "*.bin", "sort": "natural", "required": true },
+    { "id": 110, "filename": "*.sav", "sort": "single", "required": false },
+    { "id": 200, "filename": "*.iso", "sort": "natural", "required": false, "max_count": 3, "id_stride": 10 },
+    { "id": 220, "filename": "*.sub", "sort": "single", "required": false },
+    { "id": 300, "filename": "*.chd", "sort": "ascending", "required": false, "max_count": 2, "id_stride": 0 },
+    { "id": 400, "filename": "*.wav", "sort": "natural", "required": false, "max_count": 3, "id_stride": 9223372036854775807 },
+    { "id": 500, "filename": "*.mp3", "sort": "natural", "required": false, "id_stride": 18446744073709551200 }
+  ]
+}"#,
+        );
+        assert_eq!(issues.len(), 5);
+        assert_eq!(issues[0].path, "data_slots[1]");
+        assert!(issues[0]
+            .message
+            .contains("reach 110, the id of data_slots[2]"));
+        assert_eq!(issues[1].path, "data_slots[3]");
+        assert!(issues[1].message.contains("takes ids 200 to 220"));
+        assert_eq!(issues[2].path, "data_slots[5].id_stride");
+        assert!(issues
+            .iter()
+            .take(2)
+            .all(|issue| issue.severity == Severity::Warning));
+        for (issue, path) in issues[3..].iter().zip(["data_slots[6]", "data_slots[7]"]) {
+            assert_eq!(issue.path, path);
+            assert_eq!(issue.severity, Severity::Error);
+            assert!(issue.message.contains("would overflow"));
+        }
+    }
+
+    #[test]
+    fn test_validate_config_exclude() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/pcecd/core",
+  "platform_id": "pcecd",
+  "exclude": ["*.bak", "_unused/", "[Tt]humbs.db", "[abc"],
+  "data_slots": [
+    { "id": 1, "filename": "*.bin", "sort": "natural", "required": true, "exclude": ["* (Track 1).bin", 2] }
+  ]
+}"#,
+        );
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].path, "exclude[3]");
+        assert_eq!(issues[1].path, "data_slots[0].exclude[1]");
+    }
+
+    #[test]
+    fn test_validate_config_multi_disc() {
+        let config = |multi_disc: &str| {
+            format!(
+                r#"{{
+  "output": "Assets/psx/core",
+  "platform_id": "psx",
+  "data_slots": [],
+  "multi_disc": {multi_disc}
+}}"#
+            )
+        };
+
+        let issues = validate_config(&config(r#"{ "mode": "combined" }"#));
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].line, issues[0].column), (5, 17));
+        assert!(issues[0].message.contains("missing \"id_offset\""));
+
+        let issues = validate_config(&config(r#"{ "mode": "subfolder", "id_offset": 10 }"#));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "multi_disc.id_offset");
+
+        let issues = validate_config(&config(r#"{ "mode": "folders" }"#));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("unknown mode \"folders\""));
+
+        let config = |bin_slot: &str, id_offset: usize| {
+            format!(
+                r#"{{
+  "output": "Assets/psx/core",
+  "platform_id": "psx",
+  "data_slots": [
+    {{ "id": 100, "filename": "*.cue", "sort": "single", "required": true }},
+    {bin_slot}
+  ],
+  "multi_disc": {{ "mode": "combined", "id_offset": {id_offset} }}
+}}"#
+            )
+        };
+        let bounded = r#"{ "id": 101, "filename": "*.bin", "sort": "cue", "required": true, "max_count": 3 }"#;
+        assert_eq!(validate_config(&config(bounded, 4)), vec![]);
+
+        let issues = validate_config(&config(bounded, 2));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(issues[0].path, "multi_disc.id_offset");
+        assert!(issues[0].message.contains("take ids 100 to 103"));
+        assert!(issues[0].message.contains("at least 4"));
+
+        let issues = validate_config(&config(bounded, usize::MAX));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert!(issues[0].message.contains("would overflow"));
+
+        let unbounded = r#"{ "id": 101, "filename": "*.bin", "sort": "cue", "required": true }"#;
+        let issues = validate_config(&config(unbounded, 100));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0]
+            .message
+            .contains("data_slots[1] takes ids from 101 up"));
+    }
+
+    #[test]
+    fn test_validate_config_computed_memory_writes() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/pce/core",
+  "platform_id": "pce",
+  "data_slots": [{ "id": 100, "filename": "*.pce", "sort": "single", "required": true }],
+  "memory_writes": [
+    { "address": 0, "data": { "size": 100 } },
+    { "address": 4, "data": { "lookup": { "Game": 1, "Other": true }, "default": "0x0" } },
+    { "address": 8, "data": { "size": 100, "count": 100 } },
+    { "address": 12, "data": { "crc32": 100, "default": 0 } },
+    { "address": 16, "data": { "length": 100 } },
+    { "address": 20, "data": true }
+  ]
+}"#,
+        );
+        assert_eq!(issues.len(), 6);
+        assert_eq!(issues[0].path, "memory_writes[1].data.lookup.Other");
+        assert!(issues[1]
+            .message
+            .contains("exactly one of size, count, crc32, lookup"));
+        assert!(issues[2].message.contains("only used by \"lookup\""));
+        assert_eq!(issues[3].path, "memory_writes[4].data");
+        assert_eq!(issues[4].path, "memory_writes[4].data.length");
+        assert!(issues[5]
+            .message
+            .contains("a whole number, a string or an object"));
+
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/psx/core",
+  "platform_id": "psx",
+  "data_slots": [
+    { "id": 100, "filename": "*.cue", "sort": "single", "required": true },
+    { "id": 101, "filename": "*.bin", "sort": "cue", "required": true, "max_count": 3 }
+  ],
+  "multi_disc": { "mode": "combined", "id_offset": 10 },
+  "memory_writes": [
+    { "address": 0, "data": { "size": 104 } },
+    { "address": 4, "data": { "crc32": 111 } },
+    { "address": 8, "data": { "count": 102 } }
+  ],
+  "overrides": {
+    "Game": {
+      "data_slots": [{ "id": 200, "filename": "*.sbi", "sort": "single", "required": false }],
+      "memory_writes": [{ "address": 0, "data": { "size": 200 } }, { "address": 4, "data": { "count": 99 } }]
+    }
+  }
+}"#,
+        );
+        // 111 is disc 2's first track
+        assert_eq!(issues.len(), 3);
+        assert!(issues
+            .iter()
+            .all(|issue| issue.severity == Severity::Warning));
+        assert_eq!(issues[0].path, "memory_writes[0].data.size");
+        assert!(issues[0].message.contains("the id 104"));
+        assert_eq!(issues[1].path, "memory_writes[2].data.count");
+        assert_eq!(issues[2].path, "overrides.Game.memory_writes[1].data.count");
+    }
+
+    #[test]
+    fn test_validate_config_filename_template() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/arcade/core",
+  "platform_id": "arcade",
+  "data_slots": [
+    { "id": 1, "filename": "*.zip", "filename_regex": "(?P<set>\\w+)\\.zip", "sort": "single", "required": true },
+    { "id": 2, "filename": "*.bin", "sort": "single", "required": true }
+  ],
+  "filename_template": "{set|upper} {stem:3} {ext:1} {sett}",
+  "overrides": {
+    "sf2": { "filename_template": "{folder|shout}" },
+    "mk": { "filename_template": "{set}", "data_slots": [] }
+  }
+}"#,
+        );
+        assert_eq!(issues.len(), 4);
+        assert_eq!(issues[0].path, "filename_template");
+        assert!(issues[0].message.contains("no data slot has the id 3"));
+        assert!(issues[1].message.contains("{sett}"));
+        assert_eq!(issues[2].path, "overrides.sf2.filename_template");
+        assert!(issues[2].message.contains("unknown transform \"shout\""));
+        assert_eq!(issues[3].path, "overrides.mk.filename_template");
+    }
+
+    #[test]
+    fn test_validate_config_override_keys() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/ng/core",
+  "platform_id": "ng",
+  "data_slots": [],
+  "overrides": {
+    "kof9*": {},
+    "/^mslug[0-9x$/": {},
+    "kof[9": {}
+  }
+}"#,
+        );
+        assert_eq!(issues.len(), 2);
+        assert_eq!((issues[0].line, issues[0].column), (7, 5));
+        assert!(issues[0].message.starts_with("invalid key"));
+        assert_eq!(issues[1].path, "overrides.kof[9");
+    }
+
+    #[test]
+    fn test_validate_config_override_merges() {
+        let issues = validate_config(
+            r#"{
+  "output": "Assets/ng/core",
+  "platform_id": "ng",
+  "data_slots": [
+    { "id": 3, "filename": "srom", "sort": "single", "required": true },
+    { "id": 4, "filename": "prom", "sort": "single", "required": true }
+  ],
+  "overrides": {
+    "wjammss": {
+      "merge": { "data_slots": "append", "memory_writes": "merge" },
+      "remove_slots": [4, 9],
+      "data_slots": [
+        { "id": 3, "filename": "srom1", "sort": "single", "required": true },
+        { "id": 4, "filename": "prom1", "sort": "single", "required": true }
+      ]
+    }
+  }
+}"#,
+        );
+        assert_eq!(issues.len(), 3);
+        assert_eq!(issues[0].path, "overrides.wjammss.merge.memory_writes");
+        assert!(issues[0].message.contains("unknown merge mode"));
+        assert_eq!(issues[1].severity, Severity::Warning);
+        assert_eq!(issues[1].path, "overrides.wjammss.remove_slots[1]");
+        assert_eq!(issues[2].path, "overrides.wjammss.data_slots[0].id");
+    }
+
+    #[test]
+    fn test_schema_matches_validator() {
+        let schema: serde_json::Value = serde_json::from_str(CONFIG_SCHEMA).unwrap();
+        let keys = |pointer: &str| -> Vec<String> {
+            let mut keys: Vec<String> = schema
+                .pointer(pointer)
+                .and_then(|p| p.as_object())
+                .unwrap_or_else(|| panic!("{pointer} missing from the schema"))
+                .keys()
+                .cloned()
+                .collect();
+            keys.sort();
+            keys
+        };
+        let names = |fields: &[Field]| -> Vec<String> {
+            let mut names: Vec<String> = fields.iter().map(|f| String::from(f.name)).collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(keys("/properties"), names(ROOT_FIELDS));
+        assert_eq!(
+            keys("/definitions/dataSlot/properties"),
+            names(DATA_SLOT_FIELDS)
+        );
+        assert_eq!(
+            keys("/definitions/override/properties"),
+            names(OVERRIDE_FIELDS)
+        );
+        assert_eq!(
+            keys("/definitions/slotLimit/properties"),
+            names(SLOT_LIMIT_FIELDS)
+        );
+        assert_eq!(
+            keys("/definitions/multiDisc/properties"),
+            names(MULTI_DISC_FIELDS)
+        );
+        assert_eq!(
+            keys("/definitions/override/properties/merge/properties"),
+            names(MERGE_FIELDS)
+        );
+        assert_eq!(
+            schema.pointer("/definitions/mergeMode/enum"),
+            Some(&serde_json::json!(MERGE_MODES))
+        );
+        assert_eq!(
+            schema.pointer("/definitions/multiDisc/properties/mode/enum"),
+            Some(&serde_json::json!(MULTI_DISC_MODES))
+        );
+        assert_eq!(
+            schema.pointer("/definitions/dataSlot/properties/sort/enum"),
+            Some(&serde_json::json!(SORT_VALUES))
+        );
+    }
+}
@@ -1,7 +1,13 @@
+use instance_packager::{
+    BuildOptions, CollisionStrategy, CoreSummary, Event, FolderWarning, MemoryValue, PackagerError,
+    Progress, RejectReason, Severity, WriteKind,
+};
 use serde_json::json;
+use std::cell::RefCell;
+use std::sync::mpsc;
 use std::{
     fs::{self, create_dir_all, File},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use tempfile::tempdir;
 
@@ -11,7 +17,7 @@ fn make_fake_files(files: Vec<&str>) -> tempfile::TempDir {
 
     for file in files {
         let full_path = path.join(file);
-        create_dir_all(&full_path.parent().unwrap()).unwrap();
+        create_dir_all(full_path.parent().unwrap()).unwrap();
         File::create(full_path).unwrap();
     }
 
@@ -62,11 +68,13 @@ fn test_build_some_nested() {
     .unwrap();
 
     instance_packager::build_jsons_for_core(
-        &temp_path.to_path_buf(),
+        temp_path,
         "core_name",
-        true,
-        |_file_name| {},
-        |_file_name, _message| {},
+        &BuildOptions {
+            keep_file_tree: true,
+            ..BuildOptions::default()
+        },
+        &|_event| {},
     )
     .unwrap();
 
@@ -162,27 +170,1952 @@ fn test_build_too_many_files() {
     )
     .unwrap();
 
-    instance_packager::build_jsons_for_core(
-        &temp_path.to_path_buf(),
+    let events = RefCell::new(vec![]);
+    let summary = instance_packager::build_jsons_for_core(
+        temp_path,
         "core_name",
-        true,
-        |_file_name| {},
-        |file_name, message| {
-            assert_eq!(
-                file_name,
-                PathBuf::from("Assets/platform_name/core_name")
-                    .join("game_a.json")
-                    .to_str()
-                    .unwrap()
-            );
-            assert_eq!(message, "oh no - too many")
+        &BuildOptions {
+            keep_file_tree: true,
+            ..BuildOptions::default()
         },
+        &|event| events.borrow_mut().push(event),
     )
     .unwrap();
 
+    assert!(events.borrow().contains(&Event::SlotLimitExceeded {
+        path: PathBuf::from("Assets/platform_name/core_name").join("game_a.json"),
+        message: String::from("oh no - too many")
+    }));
+    assert_eq!(summary.slot_limit_exceeded, 1);
+    assert_eq!(summary.new, 0);
+
     let exists = temp_path
         .join("Assets/platform_name/core_name/game_a.json")
         .exists();
 
     assert!(!exists);
 }
+
+#[test]
+fn test_plan_core_then_write() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/game_a/game_a.cue",
+        "Assets/platform_name/common/game_a/game_a (1).bin",
+        "Assets/platform_name/common/game_b/game_b.cue",
+        "Assets/platform_name/common/game_b/game_b (1).bin",
+        "Assets/platform_name/common/not_a_game/readme.txt",
+    ]);
+    let temp_path = temp_dir.path();
+
+    let instance_package_json = r#"
+        {
+            "output":"Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots":[
+               {
+                  "id":100,
+                  "filename":"*.cue",
+                  "sort":"single",
+                  "required":true
+               },
+               {
+                  "id":101,
+                  "filename":"*.bin",
+                  "sort":"ascending",
+                  "required":true
+               }
+            ],
+            "overrides": {
+                "game_b": {
+                    "filename": "Game B"
+                }
+            }
+         }
+    "#;
+
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let plan = instance_packager::plan_core(
+        temp_path,
+        "core_name",
+        &BuildOptions::default(),
+        &|_event| {},
+    )
+    .unwrap();
+
+    assert_eq!(plan.len(), 2);
+    assert_eq!(
+        plan[0].source_folder,
+        temp_path.join("Assets/platform_name/common/game_a")
+    );
+    assert_eq!(
+        plan[0].output_path,
+        temp_path.join("Assets/platform_name/core_name/game_a.json")
+    );
+    assert_eq!(plan[0].applied_override, None);
+    assert_eq!(plan[0].instance_json.instance.data_slots.len(), 2);
+    assert_eq!(
+        plan[1].output_path,
+        temp_path.join("Assets/platform_name/core_name/Game B.json")
+    );
+    assert_eq!(plan[1].applied_override, Some(String::from("game_b")));
+
+    let output_folder = temp_path.join("Assets/platform_name/core_name");
+    assert!(!output_folder.exists());
+
+    instance_packager::write_plan(temp_path, &plan, &|_event| {});
+
+    assert!(output_folder.join("game_a.json").exists());
+    assert!(output_folder.join("Game B.json").exists());
+}
+
+#[test]
+fn test_build_reports_events() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/game_a/game_a.cue",
+        "Assets/platform_name/common/no_cue/no_cue.bin",
+    ]);
+    let temp_path = temp_dir.path();
+
+    let instance_package_json = r#"
+        {
+            "output":"Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots":[
+               {
+                  "id":100,
+                  "filename":"*.cue",
+                  "sort":"single",
+                  "required":true
+               }
+            ]
+         }
+    "#;
+
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let events = RefCell::new(vec![]);
+    instance_packager::build_jsons_for_core(
+        temp_path,
+        "core_name",
+        &BuildOptions::default(),
+        &|event| events.borrow_mut().push(event),
+    )
+    .unwrap();
+    let events = events.into_inner();
+
+    let common = PathBuf::from("Assets/platform_name/common");
+    let missing_cue = RejectReason::MissingRequiredSlot {
+        slot_id: 100,
+        filename: String::from("*.cue"),
+    };
+
+    assert_eq!(
+        events,
+        vec![
+            Event::CoreStarted {
+                core_name: String::from("core_name")
+            },
+            Event::FolderScanned {
+                folder: common.clone()
+            },
+            Event::FolderRejected {
+                folder: common.clone(),
+                reason: missing_cue.clone()
+            },
+            Event::FolderScanned {
+                folder: common.join("game_a")
+            },
+            Event::FolderScanned {
+                folder: common.join("no_cue")
+            },
+            Event::FolderRejected {
+                folder: common.join("no_cue"),
+                reason: missing_cue
+            },
+            Event::InstanceWritten {
+                path: PathBuf::from("Assets/platform_name/core_name").join("game_a.json"),
+                kind: WriteKind::New
+            },
+            Event::CoreFinished {
+                core_name: String::from("core_name"),
+                summary: CoreSummary {
+                    folders_scanned: 3,
+                    folders_rejected: 2,
+                    new: 1,
+                    ..CoreSummary::default()
+                }
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_build_skips_unchanged_files() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/game_a/game_a.cue",
+        "Assets/platform_name/common/game_b/game_b.cue",
+    ]);
+    let temp_path = temp_dir.path();
+
+    let instance_package_json = r#"
+        {
+            "output":"Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots":[
+               {
+                  "id":100,
+                  "filename":"*.cue",
+                  "sort":"single",
+                  "required":true
+               }
+            ]
+         }
+    "#;
+
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let summary = instance_packager::build_jsons_for_core(
+        temp_path,
+        "core_name",
+        &BuildOptions::default(),
+        &|_event| {},
+    )
+    .unwrap();
+    assert_eq!((summary.new, summary.updated, summary.unchanged), (2, 0, 0));
+
+    let game_b_path = temp_path.join("Assets/platform_name/core_name/game_b.json");
+    fs::write(&game_b_path, "{}").unwrap();
+
+    let summary = instance_packager::build_jsons_for_core(
+        temp_path,
+        "core_name",
+        &BuildOptions::default(),
+        &|_event| {},
+    )
+    .unwrap();
+    assert_eq!((summary.new, summary.updated, summary.unchanged), (0, 1, 1));
+    assert_ne!(fs::read_to_string(&game_b_path).unwrap(), "{}");
+}
+
+#[test]
+fn test_build_clean_orphans() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/game_a/game_a.cue",
+        "Assets/platform_name/common/game_b/game_b.cue",
+        "Assets/platform_name/core_name/hand_written.json",
+    ]);
+    let temp_path = temp_dir.path();
+
+    let instance_package_json = r#"
+        {
+            "output":"Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots":[
+               {
+                  "id":100,
+                  "filename":"*.cue",
+                  "sort":"single",
+                  "required":true
+               }
+            ]
+         }
+    "#;
+
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let output_folder = temp_path.join("Assets/platform_name/core_name");
+    let options = BuildOptions::default();
+    instance_packager::build_jsons_for_core(temp_path, "core_name", &options, &|_event| {})
+        .unwrap();
+    assert!(output_folder.join("game_b.json").exists());
+
+    fs::rename(
+        temp_path.join("Assets/platform_name/common/game_b"),
+        temp_path.join("Assets/platform_name/common/game_c"),
+    )
+    .unwrap();
+    fs::rename(
+        temp_path.join("Assets/platform_name/common/game_c/game_b.cue"),
+        temp_path.join("Assets/platform_name/common/game_c/game_c.cue"),
+    )
+    .unwrap();
+
+    // without cleaning the old file is left alone, but remembered
+    instance_packager::build_jsons_for_core(temp_path, "core_name", &options, &|_event| {})
+        .unwrap();
+    assert!(output_folder.join("game_b.json").exists());
+
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &options, &|_event| {}).unwrap();
+    let orphans = instance_packager::find_orphans(temp_path, "core_name", &plan).unwrap();
+    assert_eq!(orphans, vec![output_folder.join("game_b.json")]);
+
+    let options = BuildOptions {
+        clean_orphans: true,
+        ..BuildOptions::default()
+    };
+    let events = RefCell::new(vec![]);
+    let summary =
+        instance_packager::build_jsons_for_core(temp_path, "core_name", &options, &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+
+    assert_eq!(summary.removed, 1);
+    assert!(events.borrow().contains(&Event::InstanceRemoved {
+        path: PathBuf::from("Assets/platform_name/core_name").join("game_b.json")
+    }));
+    assert!(!output_folder.join("game_b.json").exists());
+    assert!(output_folder.join("game_a.json").exists());
+    assert!(output_folder.join("game_c.json").exists());
+    assert!(output_folder.join("hand_written.json").exists());
+}
+
+#[test]
+fn test_build_clean_keeps_files_after_scan_errors() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Game/Game.cue",
+    ]);
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "multi_disc": { "mode": "subfolder" },
+  "data_slots": [{ "id": 100, "filename": "*.cue", "sort": "single", "required": true }]
+}"#,
+    )
+    .unwrap();
+    let game_json = temp_path.join("Assets/platform_name/core_name/Game.json");
+    let options = BuildOptions {
+        clean_orphans: true,
+        ..BuildOptions::default()
+    };
+    instance_packager::build_jsons_for_core(temp_path, "core_name", &options, &|_| {}).unwrap();
+    assert!(game_json.exists());
+
+    // a playlist that can't be read leaves the game out of the plan, but it isn't an orphan
+    create_dir_all(temp_path.join("Assets/platform_name/common/Game/x.m3u")).unwrap();
+    let events = RefCell::new(vec![]);
+    let summary =
+        instance_packager::build_jsons_for_core(temp_path, "core_name", &options, &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+    assert_eq!((summary.io_errors, summary.removed), (1, 0));
+    assert!(!events
+        .borrow()
+        .iter()
+        .any(|event| matches!(event, Event::InstanceRemoved { .. })));
+    assert!(game_json.exists());
+
+    // once the folder can be read again it's written as before
+    fs::remove_dir(temp_path.join("Assets/platform_name/common/Game/x.m3u")).unwrap();
+    let summary =
+        instance_packager::build_jsons_for_core(temp_path, "core_name", &options, &|_| {}).unwrap();
+    assert_eq!((summary.unchanged, summary.removed), (1, 0));
+}
+
+#[test]
+fn test_build_clean_keeps_colliding_files() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/usa/Game/Game.cue",
+    ]);
+    let temp_path = temp_dir.path();
+    write_collision_core(temp_path, "core_name");
+    let game_json = temp_path.join("Assets/platform_name/shared/Game.json");
+    let options = BuildOptions {
+        clean_orphans: true,
+        ..BuildOptions::default()
+    };
+    let build = || {
+        let events = RefCell::new(vec![]);
+        let summary =
+            instance_packager::build_jsons_for_core(temp_path, "core_name", &options, &|event| {
+                events.borrow_mut().push(event)
+            })
+            .unwrap();
+        (summary, events.into_inner())
+    };
+    build();
+    assert!(game_json.exists());
+
+    // a second `Game` collides with the first, neither is written but the earlier file isn't an orphan
+    create_dir_all(temp_path.join("Assets/platform_name/common/jp/Game")).unwrap();
+    File::create(temp_path.join("Assets/platform_name/common/jp/Game/Game.cue")).unwrap();
+    let (summary, events) = build();
+    assert_eq!((summary.output_collisions, summary.removed), (1, 0));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, Event::InstanceRemoved { .. })));
+    assert!(game_json.exists());
+
+    // it's still remembered, so it's cleaned up once neither game wants it
+    fs::remove_dir_all(temp_path.join("Assets/platform_name/common/usa")).unwrap();
+    fs::remove_dir_all(temp_path.join("Assets/platform_name/common/jp")).unwrap();
+    let (summary, _) = build();
+    assert_eq!(summary.removed, 1);
+    assert!(!game_json.exists());
+}
+
+fn write_collision_core(temp_path: &std::path::Path, core_name: &str) {
+    let instance_package_json = r#"
+        {
+            "output":"Assets/platform_name/shared",
+            "platform_id": "platform_name",
+            "data_slots":[
+               {
+                  "id":100,
+                  "filename":"*.cue",
+                  "sort":"single",
+                  "required":true
+               }
+            ]
+         }
+    "#;
+
+    fs::write(
+        temp_path
+            .join("Cores")
+            .join(core_name)
+            .join("instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_plan_collision_strategies() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/jp/Game/game.cue",
+        "Assets/platform_name/common/usa/Game/game.cue",
+        "Assets/platform_name/common/usa/Other/other.cue",
+    ]);
+    let temp_path = temp_dir.path();
+    write_collision_core(temp_path, "core_name");
+    let output = temp_path.join("Assets/platform_name/shared");
+
+    let events = RefCell::new(vec![]);
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+
+    assert!(events.borrow().contains(&Event::OutputCollision {
+        path: PathBuf::from("Assets/platform_name/shared").join("Game.json"),
+        folders: vec![
+            PathBuf::from("Assets/platform_name/common/jp/Game"),
+            PathBuf::from("Assets/platform_name/common/usa/Game")
+        ]
+    }));
+    let written: Vec<&PathBuf> = plan
+        .iter()
+        .filter(|p| p.will_write())
+        .map(|p| &p.output_path)
+        .collect();
+    assert_eq!(written, vec![&output.join("Other.json")]);
+
+    let options = BuildOptions {
+        collision_strategy: CollisionStrategy::NumericSuffix,
+        ..BuildOptions::default()
+    };
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &options, &|_event| {}).unwrap();
+    let written: Vec<&PathBuf> = plan.iter().map(|p| &p.output_path).collect();
+    assert_eq!(
+        written,
+        vec![
+            &output.join("Game.json"),
+            &output.join("Game (2).json"),
+            &output.join("Other.json")
+        ]
+    );
+
+    let options = BuildOptions {
+        collision_strategy: CollisionStrategy::ParentPrefix,
+        ..BuildOptions::default()
+    };
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &options, &|_event| {}).unwrap();
+    let written: Vec<&PathBuf> = plan.iter().map(|p| &p.output_path).collect();
+    assert_eq!(
+        written,
+        vec![
+            &output.join("jp - Game.json"),
+            &output.join("usa - Game.json"),
+            &output.join("Other.json")
+        ]
+    );
+}
+
+#[test]
+fn test_build_cores_sharing_an_output_folder() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_a/instance-packager.json",
+        "Cores/core_b/instance-packager.json",
+        "Assets/platform_name/common/Game/game.cue",
+    ]);
+    let temp_path = temp_dir.path();
+    write_collision_core(temp_path, "core_a");
+    write_collision_core(temp_path, "core_b");
+
+    let options = BuildOptions {
+        collision_strategy: CollisionStrategy::NumericSuffix,
+        ..BuildOptions::default()
+    };
+    let results = instance_packager::build_jsons_for_cores(
+        temp_path,
+        &[String::from("core_a"), String::from("core_b")],
+        &options,
+        &|_event| {},
+    );
+
+    let summary_a = results[0].as_ref().unwrap();
+    let summary_b = results[1].as_ref().unwrap();
+    assert_eq!((summary_a.new, summary_a.output_collisions), (1, 0));
+    assert_eq!((summary_b.new, summary_b.output_collisions), (1, 1));
+
+    let output = temp_path.join("Assets/platform_name/shared");
+    assert!(output.join("Game.json").exists());
+    assert!(output.join("Game (2).json").exists());
+}
+
+#[cfg(not(feature = "parallel"))]
+#[test]
+fn test_build_cores_scans_each_core_in_turn() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_a/instance-packager.json",
+        "Cores/core_b/instance-packager.json",
+        "Assets/platform_name/common/Game/game.cue",
+    ]);
+    let temp_path = temp_dir.path();
+    write_collision_core(temp_path, "core_a");
+    write_collision_core(temp_path, "core_b");
+
+    // a game added once the first core is done is only seen if the second core hasn't been scanned yet
+    let results = instance_packager::build_jsons_for_cores(
+        temp_path,
+        &[String::from("core_a"), String::from("core_b")],
+        &BuildOptions::default(),
+        &|event| {
+            if matches!(&event, Event::CoreFinished { core_name, .. } if core_name == "core_a") {
+                let other = temp_path.join("Assets/platform_name/common/Other");
+                create_dir_all(&other).unwrap();
+                File::create(other.join("other.cue")).unwrap();
+            }
+        },
+    );
+
+    let summary_b = results[1].as_ref().unwrap();
+    assert_eq!((summary_b.new, summary_b.output_collisions), (1, 1));
+    assert!(temp_path
+        .join("Assets/platform_name/shared/Other.json")
+        .exists());
+}
+
+#[test]
+fn test_build_core_checks_other_cores_manifests() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_a/instance-packager.json",
+        "Cores/core_b/instance-packager.json",
+        "Assets/platform_name/common/Game/game.cue",
+    ]);
+    let temp_path = temp_dir.path();
+    write_collision_core(temp_path, "core_a");
+    write_collision_core(temp_path, "core_b");
+
+    let options = BuildOptions::default();
+    instance_packager::build_jsons_for_core(temp_path, "core_a", &options, &|_event| {}).unwrap();
+
+    let events = RefCell::new(vec![]);
+    let summary =
+        instance_packager::build_jsons_for_core(temp_path, "core_b", &options, &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+
+    assert_eq!((summary.new, summary.unchanged), (0, 0));
+    assert!(events.borrow().contains(&Event::OutputCollision {
+        path: PathBuf::from("Assets/platform_name/shared").join("Game.json"),
+        folders: vec![
+            PathBuf::from("Cores/core_a"),
+            PathBuf::from("Assets/platform_name/common/Game")
+        ]
+    }));
+
+    // building the same core again doesn't collide with itself
+    let summary =
+        instance_packager::build_jsons_for_core(temp_path, "core_a", &options, &|_event| {})
+            .unwrap();
+    assert_eq!((summary.unchanged, summary.output_collisions), (1, 0));
+
+    // once the other core's file is gone its manifest no longer holds on to the name
+    fs::remove_dir_all(temp_path.join("Cores/core_a")).unwrap();
+    fs::remove_file(temp_path.join("Assets/platform_name/shared/Game.json")).unwrap();
+    let summary =
+        instance_packager::build_jsons_for_core(temp_path, "core_b", &options, &|_event| {})
+            .unwrap();
+    assert_eq!((summary.new, summary.output_collisions), (1, 0));
+}
+
+#[test]
+fn test_plan_order_is_deterministic() {
+    let mut files = vec![String::from("Cores/core_name/instance-packager.json")];
+    for i in 0..40 {
+        files.push(format!("Assets/platform_name/common/game_{i:02}/game.cue"));
+    }
+    let temp_dir = make_fake_files(files.iter().map(|f| f.as_str()).collect());
+    let temp_path = temp_dir.path();
+    write_collision_core(temp_path, "core_name");
+
+    let events = RefCell::new(vec![]);
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+
+    let names: Vec<String> = plan
+        .iter()
+        .map(|p| {
+            p.output_path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    let expected: Vec<String> = (0..40).map(|i| format!("game_{i:02}.json")).collect();
+    assert_eq!(names, expected);
+
+    let scanned: Vec<PathBuf> = events
+        .into_inner()
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::FolderScanned { folder } => Some(folder),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(scanned.len(), 41);
+    assert_eq!(scanned[0], PathBuf::from("Assets/platform_name/common"));
+    assert_eq!(
+        scanned[40],
+        PathBuf::from("Assets/platform_name/common/game_39")
+    );
+}
+
+#[test]
+fn test_spawn_build_progress() {
+    let mut files = vec![String::from("Cores/core_name/instance-packager.json")];
+    for i in 0..5 {
+        files.push(format!("Assets/platform_name/common/game_{i}/game.cue"));
+    }
+    let temp_dir = make_fake_files(files.iter().map(|f| f.as_str()).collect());
+    let temp_path = temp_dir.path();
+    write_collision_core(temp_path, "core_name");
+
+    let handle = instance_packager::spawn_build(
+        temp_path.to_path_buf(),
+        vec![String::from("core_name")],
+        BuildOptions::default(),
+        |_event| {},
+    );
+    while !handle.is_finished() {
+        std::thread::yield_now();
+    }
+    assert_eq!(
+        handle.progress(),
+        Progress {
+            folders_scanned: 6,
+            estimated_total: 6
+        }
+    );
+
+    let results = handle.join();
+    assert_eq!(results[0].as_ref().unwrap().new, 5);
+}
+
+#[test]
+fn test_spawn_build_cancel_between_writes() {
+    let mut files = vec![String::from("Cores/core_name/instance-packager.json")];
+    for i in 0..5 {
+        files.push(format!("Assets/platform_name/common/game_{i}/game.cue"));
+    }
+    let temp_dir = make_fake_files(files.iter().map(|f| f.as_str()).collect());
+    let temp_path = temp_dir.path();
+    write_collision_core(temp_path, "core_name");
+
+    // the build waits for an ack after every event, so the cancel lands at a known point
+    let (event_sender, events) = mpsc::channel();
+    let (ack_sender, acks) = mpsc::channel::<()>();
+    let handle = instance_packager::spawn_build(
+        temp_path.to_path_buf(),
+        vec![String::from("core_name")],
+        BuildOptions::default(),
+        move |event| {
+            event_sender.send(event).unwrap();
+            acks.recv().unwrap();
+        },
+    );
+
+    let mut written = 0;
+    for event in events.iter() {
+        if let Event::InstanceWritten { .. } = event {
+            written += 1;
+            if written == 2 {
+                handle.cancel();
+            }
+        }
+        ack_sender.send(()).unwrap();
+    }
+
+    let results = handle.join();
+    assert!(matches!(results[0], Err(PackagerError::Cancelled)));
+
+    let output = temp_path.join("Assets/platform_name/shared");
+    let mut output_files: Vec<String> = fs::read_dir(&output)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    output_files.sort();
+    assert_eq!(
+        output_files,
+        vec![
+            ".instance-packager-core_name.manifest",
+            "game_0.json",
+            "game_1.json"
+        ]
+    );
+}
+
+#[test]
+fn test_validate_core_config_override_folders() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/core.json",
+        "Assets/platform_name/common/usa/wjammss/srom",
+    ]);
+    let path = temp_dir.path();
+    fs::write(
+        path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "data_slots": [{ "id": 3, "filename": "srom", "sort": "single", "required": true }],
+  "overrides": {
+    "wjammss": { "filename": "Windjammers" },
+    "usa": { "filename": "USA" },
+    "mslug": { "file_name": "Metal Slug" }
+  }
+}"#,
+    )
+    .unwrap();
+
+    let issues = instance_packager::validate_core_config(path, "core_name").unwrap();
+
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[0].severity, Severity::Warning);
+    assert_eq!((issues[0].line, issues[0].column), (8, 5));
+    assert_eq!(issues[0].path, "overrides.mslug");
+    assert_eq!(issues[1].severity, Severity::Error);
+    assert_eq!((issues[1].line, issues[1].column), (8, 16));
+    assert_eq!(issues[1].path, "overrides.mslug.file_name");
+}
+
+#[test]
+fn test_plan_natural_and_regex_sort() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/game_a/Track 1.bin",
+        "Assets/platform_name/common/game_a/Track 2.bin",
+        "Assets/platform_name/common/game_a/Track 10.bin",
+        "Assets/platform_name/common/game_b/B (Disc 10).bin",
+        "Assets/platform_name/common/game_b/B (Disc 9).bin",
+        "Assets/platform_name/common/game_b/B (Bonus).bin",
+    ]);
+    let temp_path = temp_dir.path();
+
+    let instance_package_json = r#"
+        {
+            "output": "Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots": [
+                { "id": 1, "filename": "*.bin", "sort": "natural", "required": true }
+            ],
+            "overrides": {
+                "game_b": {
+                    "data_slots": [
+                        {
+                            "id": 1,
+                            "filename": "*.bin",
+                            "sort": "regex",
+                            "sort_regex": "Disc (\\d+)",
+                            "required": true
+                        }
+                    ]
+                }
+            }
+        }
+    "#;
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+
+    let filenames: Vec<Vec<&str>> = plan
+        .iter()
+        .map(|planned| {
+            planned
+                .instance_json
+                .instance
+                .data_slots
+                .iter()
+                .map(|slot| slot.filename.as_str())
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        filenames,
+        vec![
+            vec!["Track 1.bin", "Track 2.bin", "Track 10.bin"],
+            vec!["B (Disc 9).bin", "B (Disc 10).bin", "B (Bonus).bin"],
+        ]
+    );
+}
+
+#[test]
+fn test_cue_sort() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/game_a/Game A.cue",
+        "Assets/platform_name/common/game_a/Game A (Track 10).bin",
+        "Assets/platform_name/common/game_a/Game A (Track 2).bin",
+        "Assets/platform_name/common/game_a/game a (track 1).bin",
+        "Assets/platform_name/common/game_a/Game A (Track 3).wav",
+        "Assets/platform_name/common/game_a/Unused.bin",
+        "Assets/platform_name/common/game_b/Game B.cue",
+        "Assets/platform_name/common/game_b/Game B (Track 1).bin",
+        "Assets/platform_name/common/game_c/Game C (Track 1).bin",
+        "Assets/platform_name/common/game_d/Game D (Track 1).bin",
+    ]);
+    let temp_path = temp_dir.path();
+    let common = temp_path.join("Assets/platform_name/common");
+    // matched by `*.cue`, but can't be read as one
+    create_dir_all(common.join("game_d/weird.cue")).unwrap();
+    fs::write(
+        common.join("game_a/Game A.cue"),
+        "FILE \"Game A (Track 1).bin\" BINARY\n  TRACK 01 MODE1/2352\n    INDEX 01 00:00:00\n\
+         FILE \"Game A (Track 2).bin\" BINARY\n  TRACK 02 AUDIO\n    INDEX 01 00:00:00\n\
+         FILE \"Game A (Track 3).wav\" WAVE\n  TRACK 03 AUDIO\n    INDEX 01 00:00:00\n\
+         FILE \"Game A (Track 10).bin\" BINARY\n  TRACK 10 AUDIO\n    INDEX 01 00:00:00\n",
+    )
+    .unwrap();
+    fs::write(
+        common.join("game_b/Game B.cue"),
+        "FILE \"Game B (Track 1).bin\" BINARY\nFILE \"Game B (Track 2).bin\" BINARY\n",
+    )
+    .unwrap();
+
+    let instance_package_json = r#"
+        {
+            "output": "Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots": [
+                { "id": 100, "filename": "*.cue", "sort": "single", "required": true },
+                { "id": 101, "filename": "*.bin", "sort": "cue", "required": true }
+            ],
+            "overrides": {
+                "game_c": {
+                    "data_slots": [
+                        { "id": 101, "filename": "*.bin", "sort": "cue", "required": true }
+                    ]
+                }
+            }
+        }
+    "#;
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let events = RefCell::new(vec![]);
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+
+    assert_eq!(plan.len(), 1);
+    let filenames: Vec<&str> = plan[0]
+        .instance_json
+        .instance
+        .data_slots
+        .iter()
+        .map(|slot| slot.filename.as_str())
+        .collect();
+    assert_eq!(
+        filenames,
+        vec![
+            "Game A.cue",
+            "game a (track 1).bin",
+            "Game A (Track 2).bin",
+            "Game A (Track 10).bin"
+        ]
+    );
+
+    let folder = |name: &str| PathBuf::from("Assets/platform_name/common").join(name);
+    let events = events.into_inner();
+    assert!(events.contains(&Event::FolderWarning {
+        folder: folder("game_a"),
+        warning: FolderWarning::CueCaseMismatch {
+            cue: String::from("Game A.cue"),
+            referenced: String::from("Game A (Track 1).bin"),
+            found: String::from("game a (track 1).bin"),
+        }
+    }));
+    assert!(events.contains(&Event::FolderWarning {
+        folder: folder("game_a"),
+        warning: FolderWarning::UnreferencedFile {
+            cue: String::from("Game A.cue"),
+            file: String::from("Unused.bin"),
+        }
+    }));
+    assert!(events.contains(&Event::FolderRejected {
+        folder: folder("game_b"),
+        reason: RejectReason::CueMissingFile {
+            cue: String::from("Game B.cue"),
+            file: String::from("Game B (Track 2).bin"),
+        }
+    }));
+    assert!(events.contains(&Event::FolderRejected {
+        folder: folder("game_c"),
+        reason: RejectReason::MissingCueSheet { slot_id: 101 }
+    }));
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::IoError { path, .. } if *path == folder("game_d/weird.cue")
+    )));
+}
+
+#[test]
+fn test_one_instance_per_file() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/flat/Game.cue",
+        "Assets/platform_name/common/flat/Game (Track 1).bin",
+        "Assets/platform_name/common/flat/Game (Track 2).bin",
+        "Assets/platform_name/common/flat/Game 2.cue",
+        "Assets/platform_name/common/flat/Game 2 (Track 1).bin",
+        "Assets/platform_name/common/flat/Lonely.cue",
+        "Assets/platform_name/common/empty/readme.txt",
+    ]);
+    let temp_path = temp_dir.path();
+
+    let instance_package_json = r#"
+        {
+            "output": "Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots": [
+                { "id": 100, "filename": "*.cue", "sort": "single", "required": true, "primary": true },
+                { "id": 101, "filename": "*.bin", "sort": "ascending", "required": true }
+            ]
+        }
+    "#;
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let events = RefCell::new(vec![]);
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+
+    let output = temp_path.join("Assets/platform_name/core_name");
+    let planned: Vec<(PathBuf, Vec<&str>)> = plan
+        .iter()
+        .map(|planned| {
+            assert_eq!(planned.instance_json.instance.data_path, "flat/");
+            (
+                planned.output_path.clone(),
+                planned
+                    .instance_json
+                    .instance
+                    .data_slots
+                    .iter()
+                    .map(|slot| slot.filename.as_str())
+                    .collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        planned,
+        vec![
+            (
+                output.join("Game 2.json"),
+                vec!["Game 2.cue", "Game 2 (Track 1).bin"]
+            ),
+            (
+                output.join("Game.json"),
+                vec!["Game.cue", "Game (Track 1).bin", "Game (Track 2).bin"]
+            ),
+        ]
+    );
+
+    let common = PathBuf::from("Assets/platform_name/common");
+    let events = events.into_inner();
+    assert!(events.contains(&Event::FolderRejected {
+        folder: common.join("flat/Lonely.cue"),
+        reason: RejectReason::MissingRequiredSlot {
+            slot_id: 101,
+            filename: String::from("*.bin")
+        }
+    }));
+    assert!(events.contains(&Event::FolderRejected {
+        folder: common.join("empty"),
+        reason: RejectReason::MissingRequiredSlot {
+            slot_id: 100,
+            filename: String::from("*.cue")
+        }
+    }));
+}
+
+fn make_multi_disc_core(files: Vec<&str>, multi_disc: &str) -> tempfile::TempDir {
+    let temp_dir = make_fake_files(files);
+    let instance_package_json = format!(
+        r#"
+        {{
+            "output": "Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots": [
+                {{ "id": 100, "filename": "*.cue", "sort": "single", "required": true }},
+                {{ "id": 101, "filename": "*.bin", "sort": "ascending", "required": true }}
+            ],
+            "multi_disc": {multi_disc}
+        }}
+    "#
+    );
+    fs::write(
+        temp_dir
+            .path()
+            .join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+    temp_dir
+}
+
+type PlannedSlots<'a> = Vec<(PathBuf, String, Vec<(usize, &'a str)>)>;
+
+fn planned_slots(plan: &[instance_packager::PlannedInstance]) -> PlannedSlots<'_> {
+    plan.iter()
+        .map(|planned| {
+            (
+                planned.output_path.clone(),
+                planned.instance_json.instance.data_path.clone(),
+                planned
+                    .instance_json
+                    .instance
+                    .data_slots
+                    .iter()
+                    .map(|slot| (slot.id, slot.filename.as_str()))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_multi_disc_folders() {
+    let files = vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Game (Disc 1)/Game (Disc 1).cue",
+        "Assets/platform_name/common/Game (Disc 1)/Game (Disc 1).bin",
+        "Assets/platform_name/common/Game (Disc 2)/Game (Disc 2).cue",
+        "Assets/platform_name/common/Game (Disc 2)/Game (Disc 2).bin",
+        "Assets/platform_name/common/Other/Other.cue",
+        "Assets/platform_name/common/Other/Other.bin",
+    ];
+
+    let temp_dir =
+        make_multi_disc_core(files.clone(), r#"{ "mode": "combined", "id_offset": 10 }"#);
+    let temp_path = temp_dir.path();
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+    let output = temp_path.join("Assets/platform_name/core_name");
+    assert_eq!(
+        planned_slots(&plan),
+        vec![
+            (
+                output.join("Game.json"),
+                String::new(),
+                vec![
+                    (100, "Game (Disc 1)/Game (Disc 1).cue"),
+                    (101, "Game (Disc 1)/Game (Disc 1).bin"),
+                    (110, "Game (Disc 2)/Game (Disc 2).cue"),
+                    (111, "Game (Disc 2)/Game (Disc 2).bin"),
+                ]
+            ),
+            (
+                output.join("Other.json"),
+                String::from("Other/"),
+                vec![(100, "Other.cue"), (101, "Other.bin")]
+            ),
+        ]
+    );
+
+    let temp_dir = make_multi_disc_core(files, r#"{ "mode": "subfolder" }"#);
+    let temp_path = temp_dir.path();
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+    let output = temp_path.join("Assets/platform_name/core_name");
+    let outputs: Vec<PathBuf> = plan.iter().map(|p| p.output_path.clone()).collect();
+    assert_eq!(
+        outputs,
+        vec![
+            output.join("Game/Game (Disc 1).json"),
+            output.join("Game/Game (Disc 2).json"),
+            output.join("Other.json"),
+        ]
+    );
+}
+
+#[test]
+fn test_multi_disc_combined_ids() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Game (Disc 1)/Game (Disc 1).cue",
+        "Assets/platform_name/common/Game (Disc 1)/Game (Disc 1) (Track 1).bin",
+        "Assets/platform_name/common/Game (Disc 1)/Game (Disc 1) (Track 2).bin",
+        "Assets/platform_name/common/Game (Disc 2)/Game (Disc 2).cue",
+        "Assets/platform_name/common/Game (Disc 2)/Game (Disc 2) (Track 1).bin",
+        "Assets/platform_name/common/Clash (Disc 1)/Clash (Disc 1).cue",
+        "Assets/platform_name/common/Clash (Disc 1)/Clash (Disc 1) (Track 1).bin",
+        "Assets/platform_name/common/Clash (Disc 1)/Clash (Disc 1) (Track 2).bin",
+        "Assets/platform_name/common/Clash (Disc 1)/Clash (Disc 1) (Track 3).bin",
+        "Assets/platform_name/common/Clash (Disc 2)/Clash (Disc 2).cue",
+        "Assets/platform_name/common/Clash (Disc 2)/Clash (Disc 2) (Track 1).bin",
+    ]);
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("Assets/platform_name/common/Game (Disc 2)/Game (Disc 2) (Track 1).bin"),
+        "1234",
+    )
+    .unwrap();
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "data_slots": [
+    { "id": 100, "filename": "*.cue", "sort": "single", "required": true },
+    { "id": 101, "filename": "*.bin", "sort": "ascending", "required": true }
+  ],
+  "memory_writes": [
+    { "address": 0, "data": { "count": 100 } },
+    { "address": 4, "data": { "count": 101 } },
+    { "address": 8, "data": { "size": 104 } }
+  ],
+  "multi_disc": { "mode": "combined", "id_offset": 3 }
+}"#,
+    )
+    .unwrap();
+
+    let events = RefCell::new(vec![]);
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+
+    assert_eq!(
+        planned_slots(&plan),
+        vec![(
+            temp_path.join("Assets/platform_name/core_name/Game.json"),
+            String::new(),
+            vec![
+                (100, "Game (Disc 1)/Game (Disc 1).cue"),
+                (101, "Game (Disc 1)/Game (Disc 1) (Track 1).bin"),
+                (102, "Game (Disc 1)/Game (Disc 1) (Track 2).bin"),
+                (103, "Game (Disc 2)/Game (Disc 2).cue"),
+                (104, "Game (Disc 2)/Game (Disc 2) (Track 1).bin"),
+            ]
+        )]
+    );
+    // worked out from every disc, not just the first
+    assert_eq!(
+        serde_json::to_value(&plan[0].instance_json.instance.memory_writes).unwrap(),
+        json!([
+            { "address": 0, "data": 2 },
+            { "address": 4, "data": 3 },
+            { "address": 8, "data": 4 }
+        ])
+    );
+
+    // disc 1's third track takes 103, where disc 2 starts
+    assert!(events.into_inner().contains(&Event::FolderRejected {
+        folder: PathBuf::from("Assets/platform_name/common/Clash (Disc 1)"),
+        reason: RejectReason::DiscIdCollision {
+            id: 103,
+            file: String::from("Clash (Disc 2)/Clash (Disc 2).cue"),
+            other: String::from("Clash (Disc 1)/Clash (Disc 1) (Track 3).bin"),
+        }
+    }));
+
+    // an offset so big disc 2's ids would overflow turns the games away rather than wrapping around
+    let config_path = temp_path.join("Cores/core_name/instance-packager.json");
+    let config = fs::read_to_string(&config_path).unwrap();
+    let config = config.replace(
+        r#""id_offset": 3"#,
+        &format!(r#""id_offset": {}"#, usize::MAX),
+    );
+    fs::write(&config_path, config).unwrap();
+    let events = RefCell::new(vec![]);
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+    assert!(plan.is_empty());
+    assert!(events.into_inner().contains(&Event::FolderRejected {
+        folder: PathBuf::from("Assets/platform_name/common/Game (Disc 1)"),
+        reason: RejectReason::IdOutOfRange { slot_id: 100 }
+    }));
+}
+
+#[test]
+fn test_multi_disc_playlist() {
+    let files = vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Game/Game.m3u",
+        "Assets/platform_name/common/Game/Game (Disc 1).cue",
+        "Assets/platform_name/common/Game/Game (Disc 1) (Track 1).bin",
+        "Assets/platform_name/common/Game/Game (Disc 2).cue",
+        "Assets/platform_name/common/Game/Game (Disc 2) (Track 1).bin",
+        "Assets/platform_name/common/Broken/Broken.m3u",
+        "Assets/platform_name/common/Broken/Broken (Disc 1).cue",
+        "Assets/platform_name/common/Broken/Broken (Disc 1).bin",
+        "Assets/platform_name/common/Weird/Weird.cue",
+        "Assets/platform_name/common/Weird/Weird.bin",
+    ];
+    let temp_dir = make_multi_disc_core(files, r#"{ "mode": "combined", "id_offset": 2 }"#);
+    let temp_path = temp_dir.path();
+    let common = temp_path.join("Assets/platform_name/common");
+    // found as the folder's playlist, but can't be read as one
+    create_dir_all(common.join("Weird/weird.m3u")).unwrap();
+    fs::write(
+        common.join("Game/Game.m3u"),
+        "#EXTM3U\r\nGame (Disc 2).cue\r\n\r\nGame (Disc 1).cue\r\n",
+    )
+    .unwrap();
+    fs::write(
+        common.join("Broken/Broken.m3u"),
+        "Broken (Disc 1).cue\nBroken (Disc 2).cue\n",
+    )
+    .unwrap();
+
+    let events = RefCell::new(vec![]);
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+
+    // the playlist's order decides the disc numbers
+    assert_eq!(
+        planned_slots(&plan),
+        vec![(
+            temp_path.join("Assets/platform_name/core_name/Game.json"),
+            String::from("Game/"),
+            vec![
+                (100, "Game (Disc 2).cue"),
+                (101, "Game (Disc 2) (Track 1).bin"),
+                (102, "Game (Disc 1).cue"),
+                (103, "Game (Disc 1) (Track 1).bin"),
+            ]
+        )]
+    );
+    let events = events.into_inner();
+    assert!(events.contains(&Event::FolderRejected {
+        folder: PathBuf::from("Assets/platform_name/common/Broken/Broken.m3u"),
+        reason: RejectReason::PlaylistMissingFile {
+            playlist: String::from("Broken.m3u"),
+            file: String::from("Broken (Disc 2).cue")
+        }
+    }));
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::IoError { path, .. }
+            if *path == Path::new("Assets/platform_name/common/Weird/weird.m3u")
+    )));
+}
+
+#[test]
+fn test_filename_regex() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Game/Game [!].cue",
+        "Assets/platform_name/common/Game/Game (Track 1).bin",
+        "Assets/platform_name/common/Game/Game (Track 2).bin",
+        "Assets/platform_name/common/Game/Game (Track 10).bin",
+        "Assets/platform_name/common/Game/Game (Track 2) (Audio).bin",
+    ]);
+    let temp_path = temp_dir.path();
+
+    let instance_package_json = r#"
+        {
+            "output": "Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots": [
+                {
+                    "id": 100,
+                    "filename": "*.cue",
+                    "filename_regex": "^(?P<name>.+) \\[!\\]\\.cue$",
+                    "sort": "single",
+                    "required": true,
+                    "as_filename": true
+                },
+                {
+                    "id": 101,
+                    "filename": "*.bin",
+                    "filename_regex": "^(?P<name>.+) \\(Track (?P<n>\\d+)\\)\\.bin$",
+                    "sort": "regex",
+                    "required": true
+                }
+            ]
+        }
+    "#;
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+
+    // the audio-only track is left out & the `name` capture drops the `[!]`
+    assert_eq!(
+        planned_slots(&plan),
+        vec![(
+            temp_path.join("Assets/platform_name/core_name/Game.json"),
+            String::from("Game/"),
+            vec![
+                (100, "Game [!].cue"),
+                (101, "Game (Track 1).bin"),
+                (102, "Game (Track 2).bin"),
+                (103, "Game (Track 10).bin"),
+            ]
+        )]
+    );
+}
+
+#[test]
+fn test_filename_template() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/usa/street fighter/sf2.zip",
+        "Assets/platform_name/common/usa/fixed/fixed.zip",
+        "Assets/platform_name/common/jp/other/mslug.zip",
+    ]);
+    let temp_path = temp_dir.path();
+
+    let instance_package_json = r#"
+        {
+            "output": "Assets/platform_name/core_name",
+            "platform_id": "platform_name",
+            "data_slots": [
+                {
+                    "id": 100,
+                    "filename": "*.zip",
+                    "filename_regex": "^(?P<set>\\w+)\\.zip$",
+                    "sort": "single",
+                    "required": true
+                }
+            ],
+            "filename_template": "{folder|title} ({set|upper}) [{parent}]",
+            "overrides": {
+                "fixed": { "filename": "Fixed Name" },
+                "other": { "filename_template": "{stem:100}.{ext:100}" }
+            }
+        }
+    "#;
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        instance_package_json,
+    )
+    .unwrap();
+
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+
+    let output = temp_path.join("Assets/platform_name/core_name");
+    let outputs: Vec<PathBuf> = plan.iter().map(|p| p.output_path.clone()).collect();
+    assert_eq!(
+        outputs,
+        vec![
+            output.join("mslug.zip.json"),
+            output.join("Fixed Name.json"),
+            output.join("Street Fighter (SF2) [usa].json"),
+        ]
+    );
+}
+
+#[test]
+fn test_filename_template_path_separators() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Game/disc/game.bin",
+    ]);
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "data_slots": [
+    { "id": 100, "filename": "*/*.bin", "filename_regex": "^(?P<name>.+)\\.bin$", "sort": "single", "required": true }
+  ],
+  "filename_template": "{name}: {folder}"
+}"#,
+    )
+    .unwrap();
+
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+    // the capture takes in the subfolder, which mustn't become a folder in the output
+    let outputs: Vec<PathBuf> = plan.iter().map(|p| p.output_path.clone()).collect();
+    assert_eq!(
+        outputs,
+        vec![temp_path.join("Assets/platform_name/core_name/disc_game_ Game.json")]
+    );
+}
+
+#[test]
+fn test_pattern_override_keys() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/kof97/srom",
+        "Assets/platform_name/common/kof98/srom",
+        "Assets/platform_name/common/kof2000/srom",
+        "Assets/platform_name/common/mslugx/srom",
+        "Assets/platform_name/common/samsho/srom",
+    ]);
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "data_slots": [{ "id": 3, "filename": "srom", "sort": "single", "required": true }],
+  "overrides": {
+    "kof*": { "filename": "KOF" },
+    "kof9*": { "filename": "KOF 9X" },
+    "kof98": { "filename": "KOF 98" },
+    "/^mslug[0-9x]$/": { "filename": "Metal Slug" },
+    "/^mslug[x0-9]$/": { "filename": "Also Metal Slug" },
+    "aof*": { "filename": "Art of Fighting" }
+  }
+}"#,
+    )
+    .unwrap();
+
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+    let applied: Vec<(String, Option<&str>)> = plan
+        .iter()
+        .map(|planned| {
+            (
+                planned
+                    .output_path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned(),
+                planned.applied_override.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        applied,
+        vec![
+            (String::from("KOF.json"), Some("kof*")),
+            (String::from("KOF 9X.json"), Some("kof9*")),
+            (String::from("KOF 98.json"), Some("kof98")),
+            (String::from("Metal Slug.json"), Some("/^mslug[0-9x]$/")),
+            (String::from("samsho.json"), None),
+        ]
+    );
+
+    let issues = instance_packager::validate_core_config(temp_path, "core_name").unwrap();
+    let issues: Vec<(Severity, &str, &str)> = issues
+        .iter()
+        .map(|issue| (issue.severity, issue.path.as_str(), issue.message.as_str()))
+        .collect();
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[0].1, "overrides./^mslug[x0-9]$/");
+    assert!(issues[0].2.contains("match \"mslugx\" equally well"));
+    assert_eq!(issues[1].1, "overrides.aof*");
+    assert!(issues[1].2.starts_with("no folder in"));
+}
+
+#[test]
+fn test_path_override_keys() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/usa/Game/game.bin",
+        "Assets/platform_name/common/jp/Game/game.bin",
+        "Assets/platform_name/common/jp/Other/game.bin",
+    ]);
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "data_slots": [{ "id": 1, "filename": "*.bin", "sort": "single", "required": true }],
+  "overrides": {
+    "Game": { "memory_writes": [{ "address": 1, "data": 1 }] },
+    "jp/Game": { "memory_writes": [{ "address": 1, "data": 2 }] },
+    "jp/*": { "memory_writes": [{ "address": 1, "data": 3 }] }
+  }
+}"#,
+    )
+    .unwrap();
+
+    let options = BuildOptions {
+        keep_file_tree: true,
+        ..BuildOptions::default()
+    };
+    let plan = instance_packager::plan_core(temp_path, "core_name", &options, &|_| {}).unwrap();
+    let applied: Vec<(PathBuf, Option<&str>, MemoryValue)> = plan
+        .iter()
+        .map(|planned| {
+            (
+                planned
+                    .output_path
+                    .strip_prefix(temp_path.join("Assets/platform_name/core_name"))
+                    .unwrap()
+                    .to_path_buf(),
+                planned.applied_override.as_deref(),
+                planned.instance_json.instance.memory_writes[0].data.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        applied,
+        vec![
+            (
+                PathBuf::from("jp/Game.json"),
+                Some("jp/Game"),
+                MemoryValue::Number(2)
+            ),
+            (
+                PathBuf::from("jp/Other.json"),
+                Some("jp/*"),
+                MemoryValue::Number(3)
+            ),
+            (
+                PathBuf::from("usa/Game.json"),
+                Some("Game"),
+                MemoryValue::Number(1)
+            ),
+        ]
+    );
+
+    let issues = instance_packager::validate_core_config(temp_path, "core_name").unwrap();
+    assert_eq!(issues, vec![]);
+}
+
+#[test]
+fn test_computed_memory_writes() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Game/track 1.bin",
+        "Assets/platform_name/common/Game/track 2.bin",
+        "Assets/platform_name/common/Other/track 1.bin",
+    ]);
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("Assets/platform_name/common/Game/track 1.bin"),
+        "12345678",
+    )
+    .unwrap();
+    // matched by `*.bin`, but there's nothing to work out a CRC32 from
+    create_dir_all(temp_path.join("Assets/platform_name/common/Weird/disc.bin")).unwrap();
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "data_slots": [{ "id": 100, "filename": "*.bin", "sort": "natural", "required": true }],
+  "memory_writes": [
+    { "address": 0, "data": { "size": 100 } },
+    { "address": 4, "data": { "count": 100 } },
+    { "address": 8, "data": { "lookup": { "Game": "0x1" } } },
+    { "address": 12, "data": { "crc32": 100 } },
+    { "address": 16, "data": { "count": 101 } }
+  ]
+}"#,
+    )
+    .unwrap();
+
+    let events = RefCell::new(vec![]);
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+            events.borrow_mut().push(event)
+        })
+        .unwrap();
+    let writes: Vec<serde_json::Value> = plan
+        .iter()
+        .map(|planned| serde_json::to_value(&planned.instance_json.instance.memory_writes).unwrap())
+        .collect();
+    assert_eq!(
+        writes,
+        vec![
+            json!([
+                { "address": 0, "data": 8 },
+                { "address": 4, "data": 2 },
+                { "address": 8, "data": "0x1" },
+                { "address": 12, "data": 0x9AE0_DAAF_u32 }
+            ]),
+            json!([
+                { "address": 0, "data": 0 },
+                { "address": 4, "data": 1 },
+                { "address": 12, "data": 0 }
+            ]),
+        ]
+    );
+    let events = events.into_inner();
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::IoError { path, .. } if *path == Path::new("Assets/platform_name/common/Weird/disc.bin")
+    )));
+    // no slot has the id 101, so the count is left out with a warning
+    assert!(events.contains(&Event::FolderWarning {
+        folder: PathBuf::from("Assets/platform_name/common/Game"),
+        warning: FolderWarning::UndeclaredSlot { slot_id: 101 }
+    }));
+}
+
+#[test]
+fn test_exclude() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Game/Game.cue",
+        "Assets/platform_name/common/Game/Game (Track 1).bin",
+        "Assets/platform_name/common/Game/Game (Track 2).bin",
+        "Assets/platform_name/common/Game/Game (Track 2).bin.bak",
+        "Assets/platform_name/common/Game/Thumbs.db",
+        "Assets/platform_name/common/_unused/Old/Old.cue",
+        "Assets/platform_name/common/Hidden/Hidden.cue",
+        "Assets/platform_name/common/Hidden/.no-instance",
+    ]);
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "exclude": ["*.bak", "_unused/", "Thumbs.db"],
+  "data_slots": [
+    { "id": 100, "filename": "*.cue", "sort": "single", "required": true },
+    { "id": 101, "filename": "*", "sort": "natural", "required": true, "exclude": ["*.cue", "* (Track 1).bin"] }
+  ]
+}"#,
+    )
+    .unwrap();
+
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+    assert_eq!(plan.len(), 1);
+    assert_eq!(plan[0].instance_json.instance.data_path, "Game/");
+    let filenames: Vec<&str> = plan[0]
+        .instance_json
+        .instance
+        .data_slots
+        .iter()
+        .map(|slot| slot.filename.as_str())
+        .collect();
+    assert_eq!(filenames, vec!["Game.cue", "Game (Track 2).bin"]);
+}
+
+#[test]
+fn test_exclude_as_filename() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Folder/Game (Old).cue",
+        "Assets/platform_name/common/Folder/Game.cue",
+    ]);
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "exclude": ["* (Old).cue"],
+  "data_slots": [
+    { "id": 100, "filename": "*.cue", "sort": "single", "required": true, "as_filename": true }
+  ]
+}"#,
+    )
+    .unwrap();
+
+    let plan =
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap();
+    assert_eq!(plan.len(), 1);
+    assert_eq!(
+        plan[0].output_path,
+        temp_path.join("Assets/platform_name/core_name/Game.json")
+    );
+}
+
+#[test]
+fn test_case_insensitive_and_normalized_names() {
+    let temp_dir = make_fake_files(vec![
+        "Cores/core_name/instance-packager.json",
+        "Assets/platform_name/common/Sonic/SONIC.BIN",
+        // an e followed by a combining accent, as macOS writes it
+        "Assets/platform_name/common/Poke\u{301}mon/pokemon.bin",
+        "Assets/platform_name/common/Pokemon/pokemon.bin",
+    ]);
+    let temp_path = temp_dir.path();
+    // the macOS & Windows file systems ignore case, so there `Pokemon.bin` is the same file
+    let pokemon_path = temp_path.join("Assets/platform_name/common/Pokemon");
+    File::create(pokemon_path.join("Pokemon.bin")).unwrap();
+    let pokemon_files: Vec<String> = fs::read_dir(&pokemon_path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    let case_sensitive = pokemon_files.len() == 2;
+    let pokemon_files = if case_sensitive {
+        vec![String::from("Pokemon.bin"), String::from("pokemon.bin")]
+    } else {
+        pokemon_files
+    };
+    let config = |flags: &str| {
+        format!(
+            r#"{{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  {flags}
+  "data_slots": [{{ "id": 1, "filename": "*.bin", "sort": "ascending", "required": true }}],
+  "overrides": {{ "Pokémon": {{ "filename": "Pocket Monsters" }} }}
+}}"#
+        )
+    };
+    let names = || -> Vec<(String, Vec<String>)> {
+        instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|_| {})
+            .unwrap()
+            .iter()
+            .map(|planned| {
+                (
+                    planned
+                        .output_path
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned(),
+                    planned
+                        .instance_json
+                        .instance
+                        .data_slots
+                        .iter()
+                        .map(|slot| slot.filename.clone())
+                        .collect(),
+                )
+            })
+            .collect()
+    };
+    let json_path = temp_path.join("Cores/core_name/instance-packager.json");
+
+    fs::write(&json_path, config("")).unwrap();
+    assert_eq!(
+        names(),
+        vec![
+            (String::from("Pokemon.json"), pokemon_files.clone()),
+            (
+                String::from("Poke\u{301}mon.json"),
+                vec![String::from("pokemon.bin")]
+            ),
+        ]
+    );
+
+    fs::write(
+        &json_path,
+        config(r#""case_insensitive": true, "normalize_unicode": true,"#),
+    )
+    .unwrap();
+    assert_eq!(
+        names(),
+        vec![
+            (String::from("Pokemon.json"), pokemon_files.clone()),
+            (
+                String::from("Pocket Monsters.json"),
+                vec![String::from("pokemon.bin")]
+            ),
+            (String::from("Sonic.json"), vec![String::from("SONIC.BIN")]),
+        ]
+    );
+
+    let warnings = RefCell::new(vec![]);
+    instance_packager::plan_core(temp_path, "core_name", &BuildOptions::default(), &|event| {
+        if let Event::FolderWarning { warning, .. } = event {
+            warnings.borrow_mut().push(warning);
+        }
+    })
+    .unwrap();
+    let expected = if case_sensitive {
+        vec![FolderWarning::CaseOnlyDifference {
+            file: String::from("Pokemon.bin"),
+            other: String::from("pokemon.bin"),
+        }]
+    } else {
+        vec![]
+    };
+    assert_eq!(warnings.into_inner(), expected);
+}
+
+#[test]
+fn test_special_characters_in_folder_names() {
+    let mut files = vec![
+        "Pocket [SD]/Cores/core_name/instance-packager.json",
+        "Pocket [SD]/Assets/platform_name/common/Bonk's Adventure [USA]/Bonk.cue",
+        "Pocket [SD]/Assets/platform_name/common/Bonk's Adventure [USA]/Bonk (Track 1).bin",
+        "Pocket [SD]/Assets/platform_name/common/Game [!]/Game.cue",
+        "Pocket [SD]/Assets/platform_name/common/Game [!]/Game.bin",
+    ];
+    // Windows doesn't allow `*` or `?` in names
+    let glob_names = cfg!(not(windows));
+    if glob_names {
+        files.extend([
+            "Pocket [SD]/Assets/platform_name/common/What*/What.cue",
+            "Pocket [SD]/Assets/platform_name/common/What*/What.bin",
+            "Pocket [SD]/Assets/platform_name/common/Why?/Why.bin",
+        ]);
+    }
+    let temp_dir = make_fake_files(files);
+    let root_path = temp_dir.path().join("Pocket [SD]");
+    fs::write(
+        root_path.join("Cores/core_name/instance-packager.json"),
+        r#"{
+  "output": "Assets/platform_name/core_name",
+  "platform_id": "platform_name",
+  "data_slots": [
+    { "id": 100, "filename": "*.cue", "sort": "single", "required": true, "as_filename": true },
+    { "id": 101, "filename": "*.bin", "sort": "ascending", "required": true }
+  ],
+  "overrides": {
+    "Game [[]!]": { "filename": "Game (Verified)" },
+    "Bonk's Adventure [USA]": { "filename": "Bonk (USA)" }
+  }
+}"#,
+    )
+    .unwrap();
+
+    let events = RefCell::new(vec![]);
+    let plan = instance_packager::plan_core(
+        &root_path,
+        "core_name",
+        &BuildOptions::default(),
+        &|event| events.borrow_mut().push(event),
+    )
+    .unwrap();
+    let built: Vec<(String, String, Vec<String>)> = plan
+        .iter()
+        .map(|planned| {
+            (
+                planned
+                    .output_path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned(),
+                planned.instance_json.instance.data_path.clone(),
+                planned
+                    .instance_json
+                    .instance
+                    .data_slots
+                    .iter()
+                    .map(|slot| slot.filename.clone())
+                    .collect(),
+            )
+        })
+        .collect();
+    let game = |name: &str, data_path: &str, files: &[&str]| {
+        (
+            String::from(name),
+            String::from(data_path),
+            files.iter().map(|file| String::from(*file)).collect(),
+        )
+    };
+    let mut expected = vec![
+        game(
+            "Bonk (USA).json",
+            "Bonk's Adventure [USA]/",
+            &["Bonk.cue", "Bonk (Track 1).bin"],
+        ),
+        game(
+            "Game (Verified).json",
+            "Game [!]/",
+            &["Game.cue", "Game.bin"],
+        ),
+    ];
+    if glob_names {
+        expected.push(game("What.json", "What*/", &["What.cue", "What.bin"]));
+    }
+    assert_eq!(built, expected);
+
+    // `Why?` has no cue, it's turned away rather than silently skipped
+    if glob_names {
+        assert!(events.into_inner().contains(&Event::FolderRejected {
+            folder: PathBuf::from("Assets/platform_name/common/Why?"),
+            reason: RejectReason::MissingRequiredSlot {
+                slot_id: 100,
+                filename: String::from("*.cue")
+            }
+        }));
+    }
+}